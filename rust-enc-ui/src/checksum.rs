@@ -0,0 +1,184 @@
+//! Public integrity trailer. It holds SHA-256 hashes of fixed-size chunks of everything
+//! before it (header and ciphertext), so storage corruption can be located without the
+//! password:
+//!
+//! `CHUNK_HASHES (32 bytes each) | TRAILER_HASH (32) | CHUNK_LEN (u32 LE) | CHUNK_COUNT (u32 LE)`
+//!
+//! `TRAILER_HASH` covers the chunk hashes and both lengths, so a damaged trailer is
+//! reported as such rather than as damaged data.
+
+use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+
+pub(crate) const CHUNK_LEN: u32 = 4096;
+
+/// Result of `run_check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    /// Bytes covered by the checksum, i.e. the file without its trailer.
+    pub checked_len: u64,
+    pub chunk_len: u32,
+    /// Byte ranges of the file whose chunk hash does not match, in file order.
+    pub damaged: Vec<Range<u64>>,
+}
+
+impl CheckReport {
+    pub fn is_intact(&self) -> bool {
+        self.damaged.is_empty()
+    }
+}
+
+pub(crate) struct Trailer {
+    chunk_len: u32,
+    hashes: Vec<[u8; 32]>,
+}
+
+impl Trailer {
+    pub fn compute(data: &[u8], chunk_len: u32) -> Self {
+        Self {
+            chunk_len,
+            hashes: data
+                .chunks(chunk_len as usize)
+                .map(|chunk| Sha256::digest(chunk).into())
+                .collect(),
+        }
+    }
+
+    fn self_hash(&self) -> [u8; 32] {
+        let mut h = Sha256::new();
+        for hash in &self.hashes {
+            h.update(hash);
+        }
+        h.update(self.chunk_len.to_le_bytes());
+        h.update((self.hashes.len() as u32).to_le_bytes());
+        h.finalize().into()
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        for hash in &self.hashes {
+            out.extend_from_slice(hash);
+        }
+        out.extend_from_slice(&self.self_hash());
+        out.extend_from_slice(&self.chunk_len.to_le_bytes());
+        out.extend_from_slice(&(self.hashes.len() as u32).to_le_bytes());
+    }
+
+    /// Reads the trailer at the end of `data` and returns it with the length of the data
+    /// it covers.
+    pub fn read(data: &[u8]) -> Result<(Self, usize)> {
+        if data.len() < 40 {
+            bail!("Checksum trailer is damaged");
+        }
+        let tail = &data[data.len() - 8..];
+        let chunk_len = u32::from_le_bytes(tail[..4].try_into().unwrap());
+        let count = u32::from_le_bytes(tail[4..].try_into().unwrap()) as usize;
+        let Some(trailer_len) = count.checked_mul(32).and_then(|n| n.checked_add(40)) else {
+            bail!("Checksum trailer is damaged");
+        };
+        let Some(covered) = data.len().checked_sub(trailer_len) else {
+            bail!("Checksum trailer is damaged");
+        };
+        let hashes = data[covered..]
+            .chunks_exact(32)
+            .take(count)
+            .map(|h| h.try_into().unwrap())
+            .collect();
+        let trailer = Self { chunk_len, hashes };
+        let stored: [u8; 32] = data[covered + count * 32..][..32].try_into().unwrap();
+        if chunk_len == 0
+            || stored != trailer.self_hash()
+            || covered.div_ceil(chunk_len as usize) != count
+        {
+            bail!("Checksum trailer is damaged");
+        }
+        Ok((trailer, covered))
+    }
+
+    /// Length of the data covered by a `CHUNK_LEN` trailer that ends a file of
+    /// `file_len` bytes, worked out from the length alone for when the trailer is
+    /// unreadable.
+    pub fn covered_len(file_len: usize) -> Option<usize> {
+        let with_hashes = file_len.checked_sub(40)?;
+        let total = |covered: usize| covered + covered.div_ceil(CHUNK_LEN as usize) * 32;
+        // `total` grows strictly, so at most one length fits
+        let (mut lo, mut hi) = (0, with_hashes);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if total(mid) < with_hashes {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        (total(lo) == with_hashes).then_some(lo)
+    }
+
+    /// Compares `data` with the stored hashes and reports the mismatching byte ranges.
+    pub fn check(&self, data: &[u8]) -> CheckReport {
+        let damaged = data
+            .chunks(self.chunk_len as usize)
+            .zip(&self.hashes)
+            .enumerate()
+            .filter(|(_, (chunk, hash))| Sha256::digest(chunk).as_slice() != *hash)
+            .map(|(i, (chunk, _))| {
+                let start = i as u64 * self.chunk_len as u64;
+                start..start + chunk.len() as u64
+            })
+            .collect();
+        CheckReport {
+            checked_len: data.len() as u64,
+            chunk_len: self.chunk_len,
+            damaged,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        Trailer::compute(&data, 4096).write(&mut data);
+        data
+    }
+
+    #[test]
+    fn trailer_roundtrip_and_locates_damage() {
+        let mut data = sample();
+        let (trailer, covered) = Trailer::read(&data).unwrap();
+        assert_eq!(covered, 10_000);
+        assert!(trailer.check(&data[..covered]).is_intact());
+
+        data[5000] ^= 1;
+        let report = trailer.check(&data[..covered]);
+        assert_eq!(report.damaged, vec![4096..8192]);
+        data[9999] ^= 1;
+        let report = trailer.check(&data[..covered]);
+        assert_eq!(report.damaged, vec![4096..8192, 8192..10_000]);
+    }
+
+    #[test]
+    fn damaged_trailer_is_detected() {
+        let clean = sample();
+        for pos in [10_000, clean.len() - 40, clean.len() - 5, clean.len() - 1] {
+            let mut data = clean.clone();
+            data[pos] ^= 1;
+            assert!(Trailer::read(&data).is_err(), "flip at {pos}");
+        }
+        assert!(Trailer::read(&clean[..clean.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn covered_len_follows_from_file_len() {
+        for len in [0, 1, 4095, 4096, 4097, 10_000, 1 << 20] {
+            let mut data = vec![0u8; len];
+            Trailer::compute(&data, CHUNK_LEN).write(&mut data);
+            assert_eq!(Trailer::covered_len(data.len()), Some(len), "{len}");
+        }
+        assert_eq!(Trailer::covered_len(39), None);
+        // 4096 bytes need one hash and 4097 two, so no file is 4096 + 32 + 40 + 1 long
+        assert_eq!(Trailer::covered_len(4096 + 32 + 40 + 1), None);
+    }
+}
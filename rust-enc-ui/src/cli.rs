@@ -0,0 +1,136 @@
+//! The command line, for scripts and terminals. Without arguments the program opens the GUI.
+//!
+//! Recipients are named by keyring nickname or fingerprint, and decryption tries the
+//! keyring's identities. Secrets are never taken as arguments, which other users can read
+//! in the process list; they come from a password source such as `env:NAME`.
+
+use anyhow::{Context, Result, anyhow, bail};
+use rust_enc_ui::{
+    DecryptOptions, EncryptOptions, keyring::Keyring, password_source::PasswordSource,
+    run_decrypt_with, run_encrypt_with,
+};
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+pub const USAGE: &str = "\
+Usage: rust-enc-ui encrypt INPUT OUTPUT [--recipient NICKNAME|FINGERPRINT]... [OPTIONS]
+       rust-enc-ui decrypt INPUT OUTPUT [OPTIONS]
+
+Options:
+  --password SOURCE            Where to read the file's password
+  --keyring PATH               Keyring file (default: ~/.config/renc/keyring)
+  --keyring-passphrase SOURCE  Where to read the keyring's master passphrase
+
+A SOURCE is env:NAME, fd:N, file:PATH or cmd:COMMAND.";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    input: PathBuf,
+    output: PathBuf,
+    password: Option<PasswordSource>,
+    recipients: Vec<String>,
+    keyring: Option<PathBuf>,
+    keyring_passphrase: Option<PasswordSource>,
+}
+
+fn parse(args: &[String]) -> Result<Args> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        Some("encrypt") => Command::Encrypt,
+        Some("decrypt") => Command::Decrypt,
+        Some(other) => bail!("Unknown command {other:?}"),
+        None => bail!("No command given"),
+    };
+    let mut paths = Vec::new();
+    let mut password = None;
+    let mut recipients = Vec::new();
+    let mut keyring = None;
+    let mut keyring_passphrase = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{arg} needs a value"));
+        match arg.as_str() {
+            "--password" => password = Some(PasswordSource::parse(value()?)?),
+            "--recipient" if command == Command::Encrypt => recipients.push(value()?.clone()),
+            "--keyring" => keyring = Some(PathBuf::from(value()?)),
+            "--keyring-passphrase" => keyring_passphrase = Some(PasswordSource::parse(value()?)?),
+            flag if flag.starts_with("--") => bail!("Unknown option {flag}"),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    let [input, output] = <[PathBuf; 2]>::try_from(paths)
+        .map_err(|_| anyhow!("Expected an input and an output file"))?;
+    Ok(Args {
+        command,
+        input,
+        output,
+        password,
+        recipients,
+        keyring,
+        keyring_passphrase,
+    })
+}
+
+/// Runs the command in `args` (without the program name).
+pub fn run(args: &[String]) -> Result<()> {
+    let args = parse(args)?;
+    let password = match &args.password {
+        Some(source) => source.read()?,
+        None => Zeroizing::new(String::new()),
+    };
+    let keyring = if args.keyring_passphrase.is_some() || !args.recipients.is_empty() {
+        Some(open_keyring(&args)?)
+    } else {
+        None
+    };
+    match args.command {
+        Command::Encrypt => {
+            if args.recipients.is_empty() && args.password.is_none() {
+                bail!("Give a --password source or at least one --recipient");
+            }
+            let mut opts = EncryptOptions::default();
+            if let Some(keyring) = &keyring {
+                for name in &args.recipients {
+                    opts.recipients.push(keyring.resolve(name)?);
+                }
+            }
+            run_encrypt_with(Some(args.input), Some(args.output), &password, &opts)?;
+        }
+        Command::Decrypt => {
+            let opts = DecryptOptions {
+                identities: keyring
+                    .iter()
+                    .flat_map(|k| k.identities().map(|(_, i)| i.clone()))
+                    .collect(),
+                ..DecryptOptions::default()
+            };
+            let report = run_decrypt_with(Some(args.input), Some(args.output), &password, &opts)?;
+            if let Some(signer) = report.signer {
+                eprintln!("Signed by {}", signer.fingerprint);
+            }
+            if report.integrity_skipped {
+                eprintln!("Integrity data was damaged and skipped");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn open_keyring(args: &Args) -> Result<Keyring> {
+    let path = args
+        .keyring
+        .clone()
+        .or_else(Keyring::default_path)
+        .context("No keyring location; pass --keyring")?;
+    let source = args
+        .keyring_passphrase
+        .as_ref()
+        .context("Opening the keyring needs --keyring-passphrase")?;
+    Keyring::open(&path, &source.read()?)
+}
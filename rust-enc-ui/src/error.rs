@@ -0,0 +1,61 @@
+use crate::inspect::format_utc;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Failures callers may want to tell apart from a generic I/O or crypto error.
+///
+/// These are returned inside `anyhow::Error`; use `downcast_ref::<Error>()` to match on them.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("KDF {param} of {value} exceeds the limit of {limit}; raise KdfLimits to allow it")]
+    KdfLimitExceeded {
+        param: &'static str,
+        value: u32,
+        limit: u32,
+    },
+    #[error("This file needs a keyfile to decrypt")]
+    KeyfileRequired,
+    #[error("Wrong password or keyfile")]
+    WrongPassword,
+    #[error("Key commitment mismatch: the header was tampered with or belongs to another file")]
+    KeyCommitmentMismatch,
+    #[error("Signature is invalid: the file was not signed by the key it names")]
+    BadSignature,
+    #[error("File is not signed, but a trusted signer is required")]
+    SignatureRequired,
+    #[error("Signed by {fingerprint}, which is not a trusted signer")]
+    UntrustedSigner { fingerprint: String },
+    #[error("File is corrupted at bytes {start}..{end}")]
+    Corrupted { start: u64, end: u64 },
+    #[error("Volume {index} of {count} is missing: {}", path.display())]
+    VolumeMissing {
+        index: u32,
+        count: u32,
+        path: PathBuf,
+    },
+    #[error("{} holds volume {found}, expected volume {expected}", path.display())]
+    VolumeOutOfOrder {
+        expected: u32,
+        found: u32,
+        path: PathBuf,
+    },
+    #[error("{} belongs to a different set of volumes", path.display())]
+    VolumeMismatch { path: PathBuf },
+    #[error("File expired on {}; allow expired files to decrypt it anyway", format_utc(*.at))]
+    Expired { at: SystemTime },
+    #[error("None of the given keys is a recipient of this file")]
+    NotARecipient,
+    #[error("The secret key is protected by a passphrase")]
+    KeyPassphraseRequired,
+    #[error("Wrong passphrase for the secret key")]
+    WrongKeyPassphrase,
+    #[error("Recovery words are valid but belong to another file")]
+    WrongRecoveryKey,
+    #[error("Share {index} does not belong to this file or is damaged")]
+    InvalidShare { index: u8 },
+    #[error("Share {index} was given more than once")]
+    DuplicateShare { index: u8 },
+    #[error("{have} shares given, but {need} are needed")]
+    NotEnoughShares { have: usize, need: u8 },
+}
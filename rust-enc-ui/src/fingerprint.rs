@@ -0,0 +1,13 @@
+//! Short, stable names for public keys, shared by recipient and signing keys.
+
+use sha2::{Digest, Sha256};
+
+/// The first 16 bytes of the SHA-256 of `key`, in colon-separated hex groups.
+pub(crate) fn of(key: &[u8]) -> String {
+    let digest = Sha256::digest(key);
+    digest[..16]
+        .chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -0,0 +1,511 @@
+use crate::kdf::KdfParams;
+use crate::keyslot::{Factors, KeySlot, PasswordSlot};
+use crate::suite::Suite;
+use anyhow::{Result, bail};
+
+pub(crate) const MAGIC: &[u8; 4] = b"RENC";
+pub(crate) const VERSION: u8 = 10;
+
+/// Header flag: the payload is framed and padded (see `padding`).
+pub(crate) const FLAG_PADDED: u16 = 1 << 0;
+/// Header flag: the plaintext ends with an Ed25519 signature block (see `signing`).
+pub(crate) const FLAG_SIGNED: u16 = 1 << 1;
+/// Header flag: the file ends with a public checksum trailer (see `checksum`).
+pub(crate) const FLAG_CHECKSUM: u16 = 1 << 2;
+/// Header flag: a Reed-Solomon parity section follows the checksum trailer (see `parity`).
+pub(crate) const FLAG_PARITY: u16 = 1 << 3;
+const KNOWN_FLAGS: u16 = FLAG_PADDED | FLAG_SIGNED | FLAG_CHECKSUM | FLAG_PARITY;
+
+/// Extension type bit: readers that do not know the type must refuse the file.
+pub(crate) const EXT_CRITICAL: u16 = 0x8000;
+/// Extension: UTF-8 comment, readable without the password.
+pub(crate) const EXT_COMMENT: u16 = 0x0001;
+/// Extension: expiry time (u64 LE seconds since the Unix epoch). Critical, so readers
+/// that cannot enforce it refuse the file.
+pub(crate) const EXT_EXPIRY: u16 = EXT_CRITICAL | 0x0002;
+/// Extension: ciphertext length (u64 LE), so the payload can be found and authenticated
+/// even when the checksum trailer or parity section after it is damaged.
+pub(crate) const EXT_PAYLOAD_LEN: u16 = 0x0003;
+/// Extension types this reader understands.
+const KNOWN_EXTENSIONS: &[u16] = &[EXT_COMMENT, EXT_EXPIRY, EXT_PAYLOAD_LEN];
+
+/// One `TYPE (u16 LE) | LENGTH (u16 LE) | VALUE` entry of the extension area.
+///
+/// Unknown non-critical extensions are kept as they are, so rewriting the header (for a
+/// password change) does not break the AAD that covers them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Extension {
+    pub kind: u16,
+    pub value: Vec<u8>,
+}
+
+impl Extension {
+    pub fn is_critical(&self) -> bool {
+        self.kind & EXT_CRITICAL != 0
+    }
+}
+
+/// Parsed file header.
+///
+/// Version 1: `MAGIC | VERSION | SALT | NONCE`
+/// Version 2: `MAGIC | VERSION | MEMORY_KIB | ITERATIONS | PARALLELISM | SALT | NONCE`,
+/// with the KDF costs as little-endian `u32` and the whole header used as AAD.
+/// Version 3: as version 2 with a `FACTORS` byte after `VERSION`.
+/// Version 4: `MAGIC | VERSION | NONCE | SLOT_COUNT | SLOTS…`. The payload is encrypted
+/// under a random DEK that each slot wraps, and only `MAGIC | VERSION | NONCE` is AAD so
+/// slots can be added or replaced without touching the ciphertext.
+/// Version 5: `MAGIC | VERSION | NONCE | COMMITMENT | SLOT_COUNT | SLOTS…`, where the
+/// 32-byte key commitment is also AAD.
+/// Version 6: as version 5 with a `SUITE` byte after `VERSION` naming the payload AEAD.
+/// Version 7: as version 6, with a key-check value in password slots.
+/// Version 8: as version 7 with a `FLAGS` field (u16 LE) after `SUITE`.
+/// Version 9: as version 8 with `EXT_LEN (u16 LE) | EXTENSIONS…` after `COMMITMENT`, also
+/// AAD.
+/// Version 10: as version 9; password slots may set `Factors::NFC`, meaning the password
+/// is normalized to Unicode NFC before key derivation.
+#[derive(Debug, Clone)]
+pub(crate) struct Header {
+    pub version: u8,
+    /// Always AES-256-GCM before version 6.
+    pub suite: Suite,
+    /// `FLAG_*` bits; zero before version 8.
+    pub flags: u16,
+    pub nonce: [u8; 12],
+    /// Commitment to the data key; `None` before version 5.
+    pub commitment: Option<[u8; 32]>,
+    /// Empty before version 9.
+    pub extensions: Vec<Extension>,
+    pub slots: Vec<KeySlot>,
+}
+
+impl Header {
+    pub fn new(
+        suite: Suite,
+        flags: u16,
+        nonce: [u8; 12],
+        commitment: [u8; 32],
+        slots: Vec<KeySlot>,
+    ) -> Self {
+        Self {
+            version: VERSION,
+            suite,
+            flags,
+            nonce,
+            commitment: Some(commitment),
+            extensions: Vec::new(),
+            slots,
+        }
+    }
+
+    pub fn extension(&self, kind: u16) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|e| e.kind == kind)
+            .map(|e| e.value.as_slice())
+    }
+
+    /// Length of the fixed part that precedes the extensions or slots.
+    fn fixed_len(version: u8) -> usize {
+        match version {
+            4 => 4 + 1 + 12,
+            5 => 4 + 1 + 12 + 32,
+            6 | 7 => 4 + 1 + 1 + 12 + 32,
+            _ => 4 + 1 + 1 + 2 + 12 + 32,
+        }
+    }
+
+    fn extensions_len(&self) -> usize {
+        self.extensions.iter().map(|e| 4 + e.value.len()).sum()
+    }
+
+    /// Length of the part that precedes the slots and is payload AAD.
+    fn aad_len(&self) -> usize {
+        match self.version {
+            ..=8 => Self::fixed_len(self.version),
+            _ => Self::fixed_len(self.version) + 2 + self.extensions_len(),
+        }
+    }
+
+    /// Encodes a version 4 or later header in its own version's layout.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let Ok(slot_count) = u8::try_from(self.slots.len()) else {
+            bail!("A file holds at most {} key slots", u8::MAX);
+        };
+        let Ok(extensions_len) = u16::try_from(self.extensions_len()) else {
+            bail!("Header extensions are longer than {} bytes", u16::MAX);
+        };
+        let mut out = Vec::with_capacity(Self::fixed_len(self.version) + 1 + self.slots.len() * 92);
+        out.extend_from_slice(MAGIC);
+        out.push(self.version);
+        if self.version >= 6 {
+            out.push(self.suite.id());
+        }
+        if self.version >= 8 {
+            out.extend_from_slice(&self.flags.to_le_bytes());
+        }
+        out.extend_from_slice(&self.nonce);
+        if let Some(commitment) = &self.commitment {
+            out.extend_from_slice(commitment);
+        }
+        if self.version >= 9 {
+            out.extend_from_slice(&extensions_len.to_le_bytes());
+            for ext in &self.extensions {
+                out.extend_from_slice(&ext.kind.to_le_bytes());
+                // Each value is part of `extensions_len`, so its length fits too
+                out.extend_from_slice(&(ext.value.len() as u16).to_le_bytes());
+                out.extend_from_slice(&ext.value);
+            }
+        }
+        out.push(slot_count);
+        for slot in &self.slots {
+            slot.write(&mut out);
+        }
+        Ok(out)
+    }
+
+    /// Parses the header at the start of `data` and returns it with its length in bytes.
+    pub fn parse(data: &[u8]) -> Result<(Self, usize)> {
+        if data.len() < 5 {
+            bail!("File too short");
+        }
+        if &data[0..4] != MAGIC {
+            bail!("Bad magic");
+        }
+        match data[4] {
+            1..=3 => Self::parse_legacy(data),
+            4..=10 => Self::parse_slotted(data),
+            _ => bail!("Unsupported version"),
+        }
+    }
+
+    fn parse_slotted(data: &[u8]) -> Result<(Self, usize)> {
+        let version = data[4];
+        let fixed_len = Self::fixed_len(version);
+        if data.len() < fixed_len + 1 {
+            bail!("File too short");
+        }
+        let mut pos = 5;
+        let suite = if version >= 6 {
+            pos += 1;
+            Suite::from_id(data[5])?
+        } else {
+            Suite::Aes256Gcm
+        };
+        let mut flags = 0;
+        if version >= 8 {
+            flags = u16::from_le_bytes([data[pos], data[pos + 1]]);
+            pos += 2;
+            if flags & !KNOWN_FLAGS != 0 {
+                bail!("Unsupported header flags 0x{flags:04x}");
+            }
+        }
+        let nonce = data[pos..pos + 12].try_into().unwrap();
+        pos += 12;
+        let commitment = (version >= 5).then(|| data[pos..pos + 32].try_into().unwrap());
+        pos = fixed_len;
+        let extensions = if version >= 9 {
+            if data.len() < pos + 2 {
+                bail!("File too short");
+            }
+            let len = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+            pos += 2;
+            let Some(area) = data.get(pos..pos + len) else {
+                bail!("File too short");
+            };
+            pos += len;
+            Self::parse_extensions(area)?
+        } else {
+            Vec::new()
+        };
+        let Some(&count) = data.get(pos) else {
+            bail!("File too short");
+        };
+        if count == 0 {
+            bail!("File has no key slots");
+        }
+        pos += 1;
+        let mut slots = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (slot, len) = KeySlot::read(&data[pos..], version)?;
+            slots.push(slot);
+            pos += len;
+        }
+        Ok((
+            Self {
+                version,
+                suite,
+                flags,
+                nonce,
+                commitment,
+                extensions,
+                slots,
+            },
+            pos,
+        ))
+    }
+
+    fn parse_extensions(mut area: &[u8]) -> Result<Vec<Extension>> {
+        let mut extensions: Vec<Extension> = Vec::new();
+        while !area.is_empty() {
+            if area.len() < 4 {
+                bail!("Malformed header extension");
+            }
+            let kind = u16::from_le_bytes([area[0], area[1]]);
+            let len = u16::from_le_bytes([area[2], area[3]]) as usize;
+            let Some(value) = area.get(4..4 + len) else {
+                bail!("Malformed header extension");
+            };
+            let ext = Extension {
+                kind,
+                value: value.to_vec(),
+            };
+            if ext.is_critical() && !KNOWN_EXTENSIONS.contains(&kind) {
+                bail!("Unsupported critical header extension 0x{kind:04x}");
+            }
+            if extensions.iter().any(|e| e.kind == kind) {
+                bail!("Duplicate header extension 0x{kind:04x}");
+            }
+            extensions.push(ext);
+            area = &area[4 + len..];
+        }
+        Ok(extensions)
+    }
+
+    /// Versions 1–3 have a single password slot whose key encrypts the payload directly.
+    fn parse_legacy(data: &[u8]) -> Result<(Self, usize)> {
+        let version = data[4];
+        let len = match version {
+            1 => 4 + 1 + 16 + 12,
+            2 => 4 + 1 + 12 + 16 + 12,
+            _ => 4 + 1 + 1 + 12 + 16 + 12,
+        };
+        if data.len() < len {
+            bail!("File too short");
+        }
+
+        let slot = match version {
+            1 => PasswordSlot {
+                factors: Factors {
+                    password: true,
+                    keyfile: false,
+                    nfc: false,
+                },
+                kdf: KdfParams::default(),
+                salt: data[5..21].try_into().unwrap(),
+                check: None,
+                wrapped: None,
+            },
+            2 => {
+                // Same layout as version 3 without the factors byte
+                let mut params = vec![Factors::PASSWORD];
+                params.extend_from_slice(&data[5..33]);
+                PasswordSlot::read_params(&params, version)?
+            }
+            _ => PasswordSlot::read_params(&data[5..34], version)?,
+        };
+        let nonce = data[len - 12..len].try_into().unwrap();
+
+        Ok((
+            Self {
+                version,
+                suite: Suite::Aes256Gcm,
+                flags: 0,
+                nonce,
+                commitment: None,
+                extensions: Vec::new(),
+                slots: vec![KeySlot::Password(slot)],
+            },
+            len,
+        ))
+    }
+
+    /// Associated data bound to the ciphertext. Version 1 files used none.
+    pub fn aad<'a>(&self, header_bytes: &'a [u8]) -> &'a [u8] {
+        match self.version {
+            1 => &[],
+            2 | 3 => header_bytes,
+            _ => &header_bytes[..self.aad_len()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v9_roundtrip() {
+        let factors = Factors {
+            password: true,
+            keyfile: true,
+            nfc: false,
+        };
+        let kdf = KdfParams {
+            memory_kib: 65536,
+            iterations: 3,
+            parallelism: 4,
+        };
+        let slot = PasswordSlot::seal(factors, kdf, [1; 16], &[5; 32], &[6; 32]).unwrap();
+        let mut header = Header::new(
+            Suite::Aes256GcmSiv,
+            FLAG_PADDED,
+            [2; 12],
+            [7; 32],
+            vec![KeySlot::Password(slot)],
+        );
+        header.extensions.push(Extension {
+            kind: 0x0123,
+            value: b"unknown to this reader".to_vec(),
+        });
+        let bytes = header.to_bytes().unwrap();
+        let (parsed, len) = Header::parse(&bytes).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(parsed.suite, Suite::Aes256GcmSiv);
+        assert_eq!(parsed.flags, FLAG_PADDED);
+        assert_eq!(parsed.nonce, [2; 12]);
+        assert_eq!(parsed.commitment, Some([7; 32]));
+        assert_eq!(parsed.extensions, header.extensions);
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+        let [KeySlot::Password(slot)] = parsed.slots.as_slice() else {
+            panic!("expected one password slot");
+        };
+        assert_eq!(slot.factors, factors);
+        assert_eq!(slot.kdf, kdf);
+        assert_eq!(slot.salt, [1; 16]);
+        assert_eq!(*slot.open(&[5; 32]).unwrap(), [6; 32]);
+        assert_eq!(
+            parsed.aad(&bytes),
+            &bytes[..4 + 1 + 1 + 2 + 12 + 32 + 2 + 4 + 22]
+        );
+    }
+
+    #[test]
+    fn v3_parses_as_unwrapped_password_slot() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(3);
+        bytes.push(0b11);
+        bytes.extend_from_slice(&1024u32.to_le_bytes());
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&[1; 16]);
+        bytes.extend_from_slice(&[2; 12]);
+        let (parsed, len) = Header::parse(&bytes).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(parsed.nonce, [2; 12]);
+        let [KeySlot::Password(slot)] = parsed.slots.as_slice() else {
+            panic!("expected one password slot");
+        };
+        assert!(slot.factors.keyfile);
+        assert_eq!(slot.kdf.memory_kib, 1024);
+        assert_eq!(slot.salt, [1; 16]);
+        assert!(slot.wrapped.is_none());
+        assert_eq!(parsed.aad(&bytes), &bytes[..]);
+    }
+
+    #[test]
+    fn v1_uses_default_kdf_and_no_aad() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend_from_slice(&[0; 28]);
+        let (parsed, len) = Header::parse(&bytes).unwrap();
+        assert_eq!(len, 33);
+        let [KeySlot::Password(slot)] = parsed.slots.as_slice() else {
+            panic!("expected one password slot");
+        };
+        assert_eq!(slot.kdf, KdfParams::default());
+        assert!(slot.factors.password && !slot.factors.keyfile);
+        assert!(parsed.aad(&bytes[..len]).is_empty());
+    }
+
+    #[test]
+    fn unknown_factor_bits_rejected() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(3);
+        bytes.push(0b100);
+        bytes.extend_from_slice(&[0; 40]);
+        assert!(Header::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn unknown_flags_rejected() {
+        let header = Header::new(Suite::Aes256Gcm, 0x8000, [0; 12], [0; 32], Vec::new());
+        let bytes = header.to_bytes().unwrap();
+        assert!(
+            Header::parse(&bytes)
+                .unwrap_err()
+                .to_string()
+                .contains("Unsupported header flags")
+        );
+    }
+
+    #[test]
+    fn unknown_critical_extension_rejected() {
+        let mut header = Header::new(Suite::Aes256Gcm, 0, [0; 12], [0; 32], Vec::new());
+        header.extensions.push(Extension {
+            kind: EXT_CRITICAL | 0x0123,
+            value: Vec::new(),
+        });
+        let bytes = header.to_bytes().unwrap();
+        assert!(
+            Header::parse(&bytes)
+                .unwrap_err()
+                .to_string()
+                .contains("Unsupported critical header extension 0x8123")
+        );
+    }
+
+    #[test]
+    fn malformed_extensions_rejected() {
+        let slot = PasswordSlot::seal(
+            Factors {
+                password: true,
+                keyfile: false,
+                nfc: false,
+            },
+            KdfParams::default(),
+            [1; 16],
+            &[5; 32],
+            &[6; 32],
+        )
+        .unwrap();
+        let mut header = Header::new(
+            Suite::Aes256Gcm,
+            0,
+            [0; 12],
+            [0; 32],
+            vec![KeySlot::Password(slot)],
+        );
+        let ext = Extension {
+            kind: 1,
+            value: vec![0; 3],
+        };
+        header.extensions = vec![ext.clone(), ext];
+        let mut bytes = header.to_bytes().unwrap();
+        assert!(Header::parse(&bytes).is_err(), "duplicate");
+
+        // Entry length running past the area
+        header.extensions.pop();
+        bytes = header.to_bytes().unwrap();
+        bytes[52 + 2 + 2] = 4;
+        assert!(Header::parse(&bytes).is_err(), "overrun");
+    }
+
+    #[test]
+    fn oversized_extensions_refused() {
+        let mut header = Header::new(Suite::Aes256Gcm, 0, [0; 12], [0; 32], Vec::new());
+        // Say, a comment too long for the extension area
+        header.extensions.push(Extension {
+            kind: 0x0123,
+            value: vec![b'x'; u16::MAX as usize - 4],
+        });
+        assert!(header.to_bytes().is_ok());
+        header.extensions[0].value.push(b'x');
+        assert!(
+            header
+                .to_bytes()
+                .unwrap_err()
+                .to_string()
+                .contains("extensions are longer than 65535 bytes")
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Hybrid public-key recipients: X25519 together with ML-KEM-768, so a file key stays
+//! safe as long as either one holds, including against ciphertexts recorded now and
+//! attacked later with a quantum computer.
+//!
+//! The key that wraps the data key is
+//! `HKDF-SHA256(salt = LABEL, ikm = SS_MLKEM | SS_X25519, info = EPHEMERAL | RECIPIENT_X25519)`,
+//! the same inputs as the X-Wing combiner.
+
+use crate::mlkem;
+use crate::signing::write_private;
+use anyhow::{Context, Result, anyhow, bail};
+use getrandom::fill;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::{fmt, fs, path::Path};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+pub(crate) use mlkem::CT_LEN;
+
+const LABEL: &[u8] = b"RENC hybrid X25519+ML-KEM-768";
+pub(crate) const SECRET_PREFIX: &str = "renc-hybrid-secret:";
+pub(crate) const PUBLIC_PREFIX: &str = "renc-hybrid-public:";
+const SECRET_LEN: usize = 32 + mlkem::SEED_LEN;
+const PUBLIC_LEN: usize = 32 + mlkem::EK_LEN;
+
+/// A recipient's private key: an X25519 secret and the ML-KEM-768 seed.
+#[derive(Clone)]
+pub struct HybridSecretKey {
+    x25519: StaticSecret,
+    seed: Zeroizing<[u8; mlkem::SEED_LEN]>,
+}
+
+/// A recipient's public key, 1216 bytes.
+#[derive(Clone, PartialEq, Eq)]
+pub struct HybridPublicKey {
+    x25519: PublicKey,
+    ek: Box<[u8; mlkem::EK_LEN]>,
+}
+
+impl HybridSecretKey {
+    pub fn public_key(&self) -> HybridPublicKey {
+        HybridPublicKey {
+            x25519: PublicKey::from(&self.x25519),
+            ek: Box::new(mlkem::public_key(&self.seed)),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8; SECRET_LEN]) -> Self {
+        let x25519: [u8; 32] = bytes[..32].try_into().unwrap();
+        Self {
+            x25519: StaticSecret::from(x25519),
+            seed: Zeroizing::new(bytes[32..].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(&self) -> Zeroizing<[u8; SECRET_LEN]> {
+        let mut bytes = Zeroizing::new([0u8; SECRET_LEN]);
+        bytes[..32].copy_from_slice(self.x25519.as_bytes());
+        bytes[32..].copy_from_slice(self.seed.as_ref());
+        bytes
+    }
+
+    /// `renc-hybrid-secret:<hex>`, the contents of a secret key file.
+    pub fn to_text(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "{SECRET_PREFIX}{}",
+            hex::encode(self.to_bytes().as_slice())
+        ))
+    }
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let Some(encoded) = text.trim().strip_prefix(SECRET_PREFIX) else {
+            bail!("Not a renc-hybrid-secret key");
+        };
+        let mut bytes = Zeroizing::new([0u8; SECRET_LEN]);
+        hex::decode_to_slice(encoded, bytes.as_mut())
+            .map_err(|_| anyhow!("Malformed secret key"))?;
+        Ok(Self::from_bytes(&bytes))
+    }
+}
+
+impl fmt::Debug for HybridSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HybridSecretKey({})", fingerprint(&self.public_key()))
+    }
+}
+
+impl HybridPublicKey {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PUBLIC_LEN);
+        bytes.extend_from_slice(self.x25519.as_bytes());
+        bytes.extend_from_slice(self.ek.as_ref());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; PUBLIC_LEN]) -> Result<Self> {
+        let x25519: [u8; 32] = bytes[..32].try_into().unwrap();
+        let ek: Box<[u8; mlkem::EK_LEN]> = Box::new(bytes[32..].try_into().unwrap());
+        if !mlkem::is_valid_public_key(&ek) {
+            bail!("Invalid ML-KEM-768 public key");
+        }
+        Ok(Self {
+            x25519: PublicKey::from(x25519),
+            ek,
+        })
+    }
+
+    /// `renc-hybrid-public:<hex>`, the contents of a public key file.
+    pub fn to_text(&self) -> String {
+        format!("{PUBLIC_PREFIX}{}", hex::encode(self.to_bytes()))
+    }
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let Some(encoded) = text.trim().strip_prefix(PUBLIC_PREFIX) else {
+            bail!("Not a renc-hybrid-public key");
+        };
+        let mut bytes = [0u8; PUBLIC_LEN];
+        hex::decode_to_slice(encoded, &mut bytes).map_err(|_| anyhow!("Malformed public key"))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl fmt::Debug for HybridPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HybridPublicKey({})", fingerprint(self))
+    }
+}
+
+pub fn generate_key() -> Result<HybridSecretKey> {
+    let mut bytes = Zeroizing::new([0u8; SECRET_LEN]);
+    fill(bytes.as_mut()).map_err(|e| anyhow!("OS RNG failed for recipient key: {e}"))?;
+    Ok(HybridSecretKey::from_bytes(&bytes))
+}
+
+/// Short name for a recipient key, shown in the keyring and in `Debug` output.
+pub fn fingerprint(key: &HybridPublicKey) -> String {
+    crate::fingerprint::of(key.to_bytes().as_slice())
+}
+
+pub fn write_secret_key(path: &Path, key: &HybridSecretKey) -> Result<()> {
+    let text = Zeroizing::new(format!("{}\n", key.to_text().as_str()));
+    write_private(path, text.as_bytes())
+        .with_context(|| format!("Writing secret key {}", path.display()))
+}
+
+pub fn read_secret_key(path: &Path) -> Result<HybridSecretKey> {
+    let text = Zeroizing::new(
+        fs::read_to_string(path)
+            .with_context(|| format!("Reading secret key {}", path.display()))?,
+    );
+    HybridSecretKey::from_text(&text).with_context(|| path.display().to_string())
+}
+
+pub fn write_public_key(path: &Path, key: &HybridPublicKey) -> Result<()> {
+    fs::write(path, format!("{}\n", key.to_text()))
+        .with_context(|| format!("Writing public key {}", path.display()))
+}
+
+pub fn read_public_key(path: &Path) -> Result<HybridPublicKey> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Reading public key {}", path.display()))?;
+    HybridPublicKey::from_text(&text).with_context(|| path.display().to_string())
+}
+
+fn combine(
+    mlkem_secret: &[u8; 32],
+    x25519_secret: &[u8; 32],
+    ephemeral: &[u8; 32],
+    recipient: &PublicKey,
+) -> Zeroizing<[u8; 32]> {
+    let mut ikm = Zeroizing::new([0u8; 64]);
+    ikm[..32].copy_from_slice(mlkem_secret);
+    ikm[32..].copy_from_slice(x25519_secret);
+    let mut info = ephemeral.to_vec();
+    info.extend_from_slice(recipient.as_bytes());
+    let mut kek = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(LABEL), ikm.as_ref())
+        .expand(&info, kek.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    kek
+}
+
+/// A fresh wrapping key with the X25519 ephemeral public key and the ML-KEM ciphertext
+/// that let the recipient derive it too.
+pub(crate) struct Encapsulated {
+    pub kek: Zeroizing<[u8; 32]>,
+    pub ephemeral: [u8; 32],
+    pub ciphertext: [u8; CT_LEN],
+}
+
+pub(crate) fn encapsulate(recipient: &HybridPublicKey) -> Result<Encapsulated> {
+    let mut random = Zeroizing::new([0u8; 64]);
+    fill(random.as_mut()).map_err(|e| anyhow!("OS RNG failed for encapsulation: {e}"))?;
+    let ephemeral_secret = StaticSecret::from(<[u8; 32]>::try_from(&random[..32]).unwrap());
+    let ephemeral = PublicKey::from(&ephemeral_secret).to_bytes();
+    let shared = ephemeral_secret.diffie_hellman(&recipient.x25519);
+    if !shared.was_contributory() {
+        bail!("Recipient has an invalid X25519 key");
+    }
+    let m: &[u8; 32] = random[32..].try_into().unwrap();
+    let (mlkem_secret, ciphertext) = mlkem::encapsulate(&recipient.ek, m)?;
+    let kek = combine(
+        &mlkem_secret,
+        shared.as_bytes(),
+        &ephemeral,
+        &recipient.x25519,
+    );
+    Ok(Encapsulated {
+        kek,
+        ephemeral,
+        ciphertext,
+    })
+}
+
+/// The wrapping key `identity` derives for a slot. For another recipient's slot this is
+/// an unrelated key, so it simply fails to unwrap.
+pub(crate) fn decapsulate(
+    identity: &HybridSecretKey,
+    ephemeral: &[u8; 32],
+    ciphertext: &[u8; CT_LEN],
+) -> Zeroizing<[u8; 32]> {
+    let shared = identity.x25519.diffie_hellman(&PublicKey::from(*ephemeral));
+    let mlkem_secret = mlkem::decapsulate(&identity.seed, ciphertext);
+    combine(
+        &mlkem_secret,
+        shared.as_bytes(),
+        ephemeral,
+        &PublicKey::from(&identity.x25519),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_files_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = generate_key().unwrap();
+        write_secret_key(&dir.path().join("id"), &key).unwrap();
+        write_public_key(&dir.path().join("id.pub"), &key.public_key()).unwrap();
+        let read = read_secret_key(&dir.path().join("id")).unwrap();
+        assert_eq!(read.public_key(), key.public_key());
+        assert_eq!(
+            read_public_key(&dir.path().join("id.pub")).unwrap(),
+            key.public_key()
+        );
+        assert!(read_secret_key(&dir.path().join("id.pub")).is_err());
+    }
+
+    #[test]
+    fn encapsulation_roundtrip() {
+        let key = generate_key().unwrap();
+        let sealed = encapsulate(&key.public_key()).unwrap();
+        let kek = decapsulate(&key, &sealed.ephemeral, &sealed.ciphertext);
+        assert_eq!(*kek, *sealed.kek);
+        let other = generate_key().unwrap();
+        let kek = decapsulate(&other, &sealed.ephemeral, &sealed.ciphertext);
+        assert_ne!(*kek, *sealed.kek);
+    }
+
+    // HKDF-SHA256 with these inputs, computed independently with Python's hmac module.
+    #[test]
+    fn combiner_vector() {
+        let kek = combine(&[1; 32], &[2; 32], &[3; 32], &PublicKey::from([4u8; 32]));
+        assert_eq!(
+            hex::encode(kek.as_slice()),
+            "b40ac43d2a3e343fe562ba53065caae912b9e5605b259ae6436fac0c3a2e9340"
+        );
+    }
+}
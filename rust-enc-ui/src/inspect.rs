@@ -0,0 +1,125 @@
+//! What can be learned about a file from its header alone, without the password.
+
+use crate::header::{
+    EXT_COMMENT, EXT_EXPIRY, EXT_PAYLOAD_LEN, FLAG_CHECKSUM, FLAG_PADDED, FLAG_PARITY, FLAG_SIGNED,
+    Header,
+};
+use crate::suite::Suite;
+use anyhow::{Result, anyhow, bail};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Header fields reported by `run_inspect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub version: u8,
+    pub suite: Suite,
+    pub padded: bool,
+    pub signed: bool,
+    pub checksum: bool,
+    pub parity: bool,
+    pub key_slots: usize,
+    /// The comment is readable by anyone, but only verified on decryption: a changed
+    /// comment makes decryption fail.
+    pub comment: Option<String>,
+    /// After this time decryption is refused unless `DecryptOptions::allow_expired` is set.
+    pub expires: Option<SystemTime>,
+}
+
+impl FileInfo {
+    pub(crate) fn from_header(header: &Header) -> Result<Self> {
+        Ok(Self {
+            version: header.version,
+            suite: header.suite,
+            padded: header.flags & FLAG_PADDED != 0,
+            signed: header.flags & FLAG_SIGNED != 0,
+            checksum: header.flags & FLAG_CHECKSUM != 0,
+            parity: header.flags & FLAG_PARITY != 0,
+            key_slots: header.slots.len(),
+            comment: comment(header)?,
+            expires: expiry(header)?,
+        })
+    }
+}
+
+pub(crate) fn comment(header: &Header) -> Result<Option<String>> {
+    header
+        .extension(EXT_COMMENT)
+        .map(|value| {
+            String::from_utf8(value.to_vec()).map_err(|_| anyhow!("Comment is not valid UTF-8"))
+        })
+        .transpose()
+}
+
+pub(crate) fn expiry(header: &Header) -> Result<Option<SystemTime>> {
+    let Some(value) = header.extension(EXT_EXPIRY) else {
+        return Ok(None);
+    };
+    let Ok(secs) = <[u8; 8]>::try_from(value) else {
+        bail!("Malformed expiry extension");
+    };
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(u64::from_le_bytes(secs)))
+        .map(Some)
+        .ok_or_else(|| anyhow!("Malformed expiry extension"))
+}
+
+pub(crate) fn payload_len(header: &Header) -> Result<Option<u64>> {
+    header
+        .extension(EXT_PAYLOAD_LEN)
+        .map(|value| {
+            <[u8; 8]>::try_from(value)
+                .map(u64::from_le_bytes)
+                .map_err(|_| anyhow!("Malformed payload length extension"))
+        })
+        .transpose()
+}
+
+/// Encodes an expiry time as seconds since the Unix epoch, rounded up.
+pub(crate) fn expiry_value(time: SystemTime) -> Vec<u8> {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs() + u64::from(since.subsec_nanos() > 0);
+    secs.to_le_bytes().to_vec()
+}
+
+/// `YYYY-MM-DD HH:MM:SS UTC`, for messages about expiry times.
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_utc_dates() {
+        for (secs, text) in [
+            (0, "1970-01-01 00:00:00 UTC"),
+            (951_782_400, "2000-02-29 00:00:00 UTC"),
+            (1_700_000_000, "2023-11-14 22:13:20 UTC"),
+            (4_102_444_799, "2099-12-31 23:59:59 UTC"),
+        ] {
+            assert_eq!(format_utc(UNIX_EPOCH + Duration::from_secs(secs)), text);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::error::Error;
+use anyhow::{Context, Result, anyhow};
+use argon2::{Algorithm, Argon2, Params, Version};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::Path};
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+/// Argon2id cost parameters, stored in the file header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// The `Argon2::default()` costs, which is also what version 1 files used.
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Upper bounds on the KDF costs a file header may ask for.
+///
+/// Headers are untrusted input, so decryption refuses anything above these
+/// before running Argon2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfLimits {
+    pub max_memory_kib: u32,
+    pub max_iterations: u32,
+    pub max_parallelism: u32,
+}
+
+impl Default for KdfLimits {
+    fn default() -> Self {
+        Self {
+            max_memory_kib: 1024 * 1024, // 1 GiB
+            max_iterations: 16,
+            max_parallelism: 16,
+        }
+    }
+}
+
+impl KdfLimits {
+    pub fn check(&self, params: &KdfParams) -> Result<(), Error> {
+        let checks = [
+            ("memory (KiB)", params.memory_kib, self.max_memory_kib),
+            ("iterations", params.iterations, self.max_iterations),
+            ("parallelism", params.parallelism, self.max_parallelism),
+        ];
+        for (param, value, limit) in checks {
+            if value > limit {
+                return Err(Error::KdfLimitExceeded {
+                    param,
+                    value,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SHA-256 of a keyfile's contents. Any file works; only its bytes matter.
+pub(crate) fn hash_keyfile(path: &Path) -> Result<[u8; 32]> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Opening keyfile {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Reading keyfile {}", path.display()))?;
+    Ok(hasher.finalize().into())
+}
+
+/// The password as key derivation sees it. With `nfc` it is normalized to Unicode NFC, so
+/// an "é" typed as one code point (Linux, Windows) or as "e" plus a combining accent
+/// (macOS) gives the same key; without it the bytes are used as typed, as files before
+/// version 10 were written.
+pub(crate) fn password_input(password: &str, nfc: bool) -> Zeroizing<String> {
+    if nfc {
+        Zeroizing::new(password.nfc().collect())
+    } else {
+        Zeroizing::new(password.to_string())
+    }
+}
+
+/// Builds the Argon2 input from the factors in use: the password bytes followed by the
+/// keyfile hash. A password alone gives exactly its bytes, as in older versions.
+pub(crate) fn secret_input(
+    password: Option<&str>,
+    keyfile_hash: Option<&[u8; 32]>,
+) -> Zeroizing<Vec<u8>> {
+    let mut secret = Zeroizing::new(Vec::new());
+    if let Some(password) = password {
+        secret.extend_from_slice(password.as_bytes());
+    }
+    if let Some(hash) = keyfile_hash {
+        secret.extend_from_slice(hash);
+    }
+    secret
+}
+
+pub(crate) fn derive_key(secret: &[u8], salt: &[u8], params: &KdfParams) -> Result<[u8; 32]> {
+    let params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(32),
+    )
+    .map_err(|e| anyhow!("Invalid KDF parameters: {e}"))?;
+    let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let mut key = [0u8; 32];
+
+    argon
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|_| anyhow!("Key derivation failed"))?;
+
+    Ok(key)
+}
+
+/// Splits a data key into the key that encrypts the payload and a public commitment to it.
+///
+/// AES-GCM is not key-committing: a crafted ciphertext can authenticate under more than one
+/// key. Storing the commitment in the header and checking it before decrypting ties the
+/// file to exactly one data key.
+pub(crate) fn split_data_key(dek: &[u8; 32]) -> (Zeroizing<[u8; 32]>, [u8; 32]) {
+    let hk = Hkdf::<Sha256>::new(None, dek);
+    let mut payload_key = Zeroizing::new([0u8; 32]);
+    let mut commitment = [0u8; 32];
+    hk.expand(b"RENC payload key", payload_key.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    hk.expand(b"RENC key commitment", &mut commitment)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    (payload_key, commitment)
+}
+
+/// Key-check value stored in password slots: HMAC-SHA256 of a fixed label under the
+/// derived key, truncated to 16 bytes.
+///
+/// It lets a wrong password be reported straight after key derivation. Testing a guess
+/// against it still costs a full Argon2 run, the same as testing it against the wrapped key.
+pub(crate) fn key_check_value(kek: &[u8; 32]) -> [u8; 16] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(kek).expect("HMAC accepts any key length");
+    mac.update(b"RENC key check");
+    mac.finalize().into_bytes()[..16].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_params_match_argon2_default() {
+        let salt = [7u8; 16];
+        let mut expected = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(b"pw", &salt, &mut expected)
+            .unwrap();
+        assert_eq!(
+            derive_key(b"pw", &salt, &KdfParams::default()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn limits_name_the_exceeded_parameter() {
+        let params = KdfParams {
+            iterations: 100,
+            ..KdfParams::default()
+        };
+        let err = KdfLimits::default().check(&params).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::KdfLimitExceeded {
+                param: "iterations",
+                value: 100,
+                limit: 16
+            }
+        ));
+    }
+
+    #[test]
+    fn secret_input_appends_keyfile_hash() {
+        let hash = [9u8; 32];
+        assert_eq!(*secret_input(Some("pw"), None), b"pw");
+        assert_eq!(*secret_input(None, Some(&hash)), hash);
+        let both = secret_input(Some("pw"), Some(&hash));
+        assert_eq!(&both[..2], b"pw");
+        assert_eq!(&both[2..], hash);
+    }
+
+    #[test]
+    fn nfc_input_ignores_composition() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(
+            *password_input(decomposed, true),
+            *password_input(composed, true)
+        );
+        assert_ne!(
+            *password_input(decomposed, false),
+            *password_input(composed, false)
+        );
+    }
+
+    #[test]
+    fn split_data_key_separates_outputs() {
+        let (key_a, commit_a) = split_data_key(&[1; 32]);
+        let (key_b, commit_b) = split_data_key(&[2; 32]);
+        assert_ne!(*key_a, [1; 32]);
+        assert_ne!(*key_a, commit_a);
+        assert_ne!(commit_a, commit_b);
+        assert_ne!(*key_a, *key_b);
+        assert_eq!(split_data_key(&[1; 32]).1, commit_a);
+    }
+}
@@ -0,0 +1,362 @@
+//! A local keyring: your own identities and colleagues' public keys under nicknames, in
+//! one file encrypted under a master passphrase.
+//!
+//! The file is `MAGIC | VERSION | MEMORY_KIB | ITERATIONS | PARALLELISM | SALT | NONCE |
+//! CIPHERTEXT`, the ciphertext being AES-256-GCM under the Argon2id key of the master
+//! passphrase with everything before it as AAD. Contacts are encrypted too, so nobody can
+//! swap a colleague's key without the passphrase. From version 2 the passphrase is
+//! normalized to NFC first; version 1 keyrings keep it as typed until it is changed.
+//! The plaintext has one entry per line:
+//!
+//! ```text
+//! contact NICKNAME PUBLIC_KEY_TEXT
+//! identity NICKNAME HEX(SECRET_KEY_TEXT)
+//! ```
+
+use crate::kdf::{KdfLimits, KdfParams, derive_key, password_input};
+use crate::recipient::{Identity, Recipient};
+use crate::signing::write_private;
+use aes_gcm::{
+    Aes256Gcm, KeyInit,
+    aead::{Aead, Key, Payload},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use getrandom::fill;
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"RENCKEYS";
+const VERSION: u8 = 2;
+const PREFIX_LEN: usize = 8 + 1 + 12 + 16 + 12;
+
+/// A colleague's public key under a nickname.
+#[derive(Debug, Clone)]
+pub struct Contact {
+    pub nickname: String,
+    pub recipient: Recipient,
+}
+
+impl Contact {
+    pub fn fingerprint(&self) -> String {
+        self.recipient.fingerprint()
+    }
+}
+
+/// An open keyring. Changes are kept in memory until `save`.
+pub struct Keyring {
+    path: PathBuf,
+    version: u8,
+    kdf: KdfParams,
+    salt: [u8; 16],
+    key: Zeroizing<[u8; 32]>,
+    contacts: Vec<Contact>,
+    identities: Vec<(String, Identity)>,
+}
+
+impl Keyring {
+    /// `renc/keyring` under the user's configuration directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(config.join("renc").join("keyring"))
+    }
+
+    /// Creates an empty keyring at `path`, which must not exist yet.
+    pub fn create(path: &Path, master: &str) -> Result<Self> {
+        if path.exists() {
+            bail!("{} already exists", path.display());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+        }
+        let kdf = KdfParams::default();
+        let mut salt = [0u8; 16];
+        fill(&mut salt).map_err(|e| anyhow!("OS RNG failed for salt: {e}"))?;
+        let keyring = Self {
+            path: path.to_path_buf(),
+            version: VERSION,
+            kdf,
+            salt,
+            key: master_key(master, VERSION, &salt, &kdf)?,
+            contacts: Vec::new(),
+            identities: Vec::new(),
+        };
+        keyring.save()?;
+        Ok(keyring)
+    }
+
+    pub fn open(path: &Path, master: &str) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Reading {}", path.display()))?;
+        if data.len() < PREFIX_LEN || data[..8] != MAGIC[..] {
+            bail!("{} is not a keyring", path.display());
+        }
+        let version = data[8];
+        if !(1..=VERSION).contains(&version) {
+            bail!("Unsupported keyring version {version}");
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        let kdf = KdfParams {
+            memory_kib: u32_at(9),
+            iterations: u32_at(13),
+            parallelism: u32_at(17),
+        };
+        KdfLimits::default().check(&kdf)?;
+        let salt: [u8; 16] = data[21..37].try_into().unwrap();
+        let key = master_key(master, version, &salt, &kdf)?;
+
+        let (aad, rest) = data.split_at(PREFIX_LEN - 12);
+        let (nonce, ciphertext) = rest.split_at(12);
+        let plaintext = Zeroizing::new(
+            Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_ref()))
+                .decrypt(
+                    nonce.into(),
+                    Payload {
+                        msg: ciphertext,
+                        aad,
+                    },
+                )
+                .map_err(|_| anyhow!("Wrong keyring passphrase, or the keyring is damaged"))?,
+        );
+        let text = std::str::from_utf8(&plaintext).context("Keyring contents are not text")?;
+
+        let mut keyring = Self {
+            path: path.to_path_buf(),
+            version,
+            kdf,
+            salt,
+            key,
+            contacts: Vec::new(),
+            identities: Vec::new(),
+        };
+        for (i, line) in text.lines().enumerate() {
+            let mut fields = line.splitn(3, ' ');
+            let (Some(kind), Some(nickname), Some(value)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                bail!("Keyring line {} is malformed", i + 1);
+            };
+            match kind {
+                "contact" => keyring.contacts.push(Contact {
+                    nickname: nickname.to_string(),
+                    recipient: Recipient::from_text(value)
+                        .with_context(|| format!("Contact {nickname}"))?,
+                }),
+                "identity" => {
+                    let secret = Zeroizing::new(
+                        hex::decode(value)
+                            .map_err(|_| anyhow!("Identity {nickname} is malformed"))?,
+                    );
+                    let secret = std::str::from_utf8(&secret)
+                        .map_err(|_| anyhow!("Identity {nickname} is malformed"))?;
+                    let identity = Identity::from_text(secret, None)
+                        .with_context(|| format!("Identity {nickname}"))?;
+                    keyring.identities.push((nickname.to_string(), identity));
+                }
+                _ => bail!("Keyring line {} has unknown entry {kind:?}", i + 1),
+            }
+        }
+        Ok(keyring)
+    }
+
+    /// Encrypts the keyring under a fresh nonce and replaces the file.
+    pub fn save(&self) -> Result<()> {
+        let mut text = Zeroizing::new(String::new());
+        for contact in &self.contacts {
+            let key = contact.recipient.to_text();
+            writeln!(text, "contact {} {key}", contact.nickname)?;
+        }
+        for (nickname, identity) in &self.identities {
+            let secret = Zeroizing::new(hex::encode(identity.to_text().as_bytes()));
+            writeln!(text, "identity {nickname} {}", secret.as_str())?;
+        }
+
+        let mut out = MAGIC.to_vec();
+        out.push(self.version);
+        out.extend_from_slice(&self.kdf.memory_kib.to_le_bytes());
+        out.extend_from_slice(&self.kdf.iterations.to_le_bytes());
+        out.extend_from_slice(&self.kdf.parallelism.to_le_bytes());
+        out.extend_from_slice(&self.salt);
+        let mut nonce = [0u8; 12];
+        fill(&mut nonce).map_err(|e| anyhow!("OS RNG failed for nonce: {e}"))?;
+        let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(self.key.as_ref()))
+            .encrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: text.as_bytes(),
+                    aad: &out,
+                },
+            )
+            .map_err(|_| anyhow!("Keyring encryption failed"))?;
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+
+        // Write beside the keyring and rename, so a crash cannot leave half a keyring
+        let temp = self.path.with_extension("tmp");
+        write_private(&temp, &out).with_context(|| format!("Writing {}", temp.display()))?;
+        fs::rename(&temp, &self.path).with_context(|| format!("Replacing {}", self.path.display()))
+    }
+
+    /// Re-encrypts the keyring under a new master passphrase on the next `save`, in the
+    /// current version.
+    pub fn set_master_passphrase(&mut self, master: &str) -> Result<()> {
+        fill(&mut self.salt).map_err(|e| anyhow!("OS RNG failed for salt: {e}"))?;
+        self.key = master_key(master, VERSION, &self.salt, &self.kdf)?;
+        self.version = VERSION;
+        Ok(())
+    }
+
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    pub fn add_contact(&mut self, nickname: &str, recipient: Recipient) -> Result<()> {
+        check_nickname(nickname)?;
+        if self.contacts.iter().any(|c| c.nickname == nickname) {
+            bail!("A contact named {nickname} already exists");
+        }
+        self.contacts.push(Contact {
+            nickname: nickname.to_string(),
+            recipient,
+        });
+        Ok(())
+    }
+
+    pub fn remove_contact(&mut self, nickname: &str) -> bool {
+        let before = self.contacts.len();
+        self.contacts.retain(|c| c.nickname != nickname);
+        self.contacts.len() != before
+    }
+
+    /// The contact with this nickname or fingerprint.
+    pub fn resolve(&self, name: &str) -> Result<Recipient> {
+        self.contacts
+            .iter()
+            .find(|c| c.nickname == name)
+            .or_else(|| self.contacts.iter().find(|c| c.fingerprint() == name))
+            .map(|c| c.recipient.clone())
+            .ok_or_else(|| anyhow!("No contact named {name} in the keyring"))
+    }
+
+    /// Nicknames and keys of your identities.
+    pub fn identities(&self) -> impl Iterator<Item = (&str, &Identity)> {
+        self.identities.iter().map(|(n, i)| (n.as_str(), i))
+    }
+
+    pub fn add_identity(&mut self, nickname: &str, identity: Identity) -> Result<()> {
+        check_nickname(nickname)?;
+        if self.identities.iter().any(|(n, _)| n == nickname) {
+            bail!("An identity named {nickname} already exists");
+        }
+        self.identities.push((nickname.to_string(), identity));
+        Ok(())
+    }
+
+    pub fn remove_identity(&mut self, nickname: &str) -> bool {
+        let before = self.identities.len();
+        self.identities.retain(|(n, _)| n != nickname);
+        self.identities.len() != before
+    }
+}
+
+/// The key of the master passphrase in a keyring of `version`.
+fn master_key(
+    master: &str,
+    version: u8,
+    salt: &[u8; 16],
+    kdf: &KdfParams,
+) -> Result<Zeroizing<[u8; 32]>> {
+    let master = password_input(master, version >= 2);
+    Ok(Zeroizing::new(derive_key(master.as_bytes(), salt, kdf)?))
+}
+
+fn check_nickname(nickname: &str) -> Result<()> {
+    if nickname.is_empty() || nickname.chars().any(char::is_whitespace) {
+        bail!("Nicknames must be non-empty and without spaces");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hybrid;
+
+    #[test]
+    fn keyring_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("renc").join("keyring");
+        let mut keyring = Keyring::create(&path, "master").unwrap();
+        assert!(Keyring::create(&path, "master").is_err());
+
+        let mine = hybrid::generate_key().unwrap();
+        let alice = hybrid::generate_key().unwrap().public_key();
+        keyring.add_identity("work", mine.clone().into()).unwrap();
+        keyring.add_contact("alice", alice.clone().into()).unwrap();
+        assert!(keyring.add_contact("alice", alice.clone().into()).is_err());
+        assert!(
+            keyring
+                .add_contact("bob smith", alice.clone().into())
+                .is_err()
+        );
+        keyring.save().unwrap();
+
+        assert!(Keyring::open(&path, "wrong").is_err());
+        let keyring = Keyring::open(&path, "master").unwrap();
+        let alice = Recipient::from(alice);
+        assert_eq!(keyring.resolve("alice").unwrap(), alice);
+        assert_eq!(keyring.resolve(&alice.fingerprint()).unwrap(), alice);
+        assert!(keyring.resolve("bob").is_err());
+        let (nickname, identity) = keyring.identities().next().unwrap();
+        assert_eq!(nickname, "work");
+        assert_eq!(identity.recipient(), mine.public_key().into());
+    }
+
+    #[test]
+    fn master_passphrase_can_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keyring");
+        let mut keyring = Keyring::create(&path, "old").unwrap();
+        keyring.set_master_passphrase("new").unwrap();
+        keyring.save().unwrap();
+        assert!(Keyring::open(&path, "old").is_err());
+        assert!(Keyring::open(&path, "new").is_ok());
+    }
+
+    #[test]
+    fn version_1_passphrases_are_used_as_typed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keyring");
+        let (composed, decomposed) = ("caf\u{e9}", "cafe\u{301}");
+        let kdf = KdfParams::default();
+        let salt = [7; 16];
+        let keyring = Keyring {
+            path: path.clone(),
+            version: 1,
+            kdf,
+            salt,
+            key: master_key(decomposed, 1, &salt, &kdf).unwrap(),
+            contacts: Vec::new(),
+            identities: Vec::new(),
+        };
+        keyring.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[8], 1);
+
+        assert!(Keyring::open(&path, composed).is_err());
+        let mut keyring = Keyring::open(&path, decomposed).unwrap();
+        keyring.save().unwrap();
+        assert!(Keyring::open(&path, decomposed).is_ok());
+
+        // A new passphrase brings the keyring up to date
+        keyring.set_master_passphrase(decomposed).unwrap();
+        keyring.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[8], VERSION);
+        assert!(Keyring::open(&path, composed).is_ok());
+    }
+}
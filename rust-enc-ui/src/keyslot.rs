@@ -0,0 +1,663 @@
+//! Key slots. Each slot wraps the file's random data-encryption key (DEK) under a
+//! key-encryption key (KEK) derived from one way of unlocking the file.
+
+use crate::hybrid::{self, CT_LEN, HybridPublicKey, HybridSecretKey};
+use crate::kdf::{KdfParams, key_check_value};
+use crate::ssh::{self, TAG_LEN};
+use aes_gcm::{
+    Aes256Gcm, KeyInit,
+    aead::{Aead, Key, Payload},
+};
+use anyhow::{Result, anyhow, bail};
+use getrandom::fill;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+pub(crate) const KIND_PASSWORD: u8 = 1;
+pub(crate) const KIND_SHARES: u8 = 2;
+pub(crate) const KIND_RECOVERY: u8 = 3;
+pub(crate) const KIND_HYBRID: u8 = 4;
+pub(crate) const KIND_SSH: u8 = 5;
+
+/// Which secrets were mixed into the KDF input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Factors {
+    pub password: bool,
+    pub keyfile: bool,
+    /// The password was normalized to NFC first (from version 10). Older slots used its
+    /// bytes as typed.
+    pub nfc: bool,
+}
+
+impl Factors {
+    pub const PASSWORD: u8 = 0b001;
+    pub const KEYFILE: u8 = 0b010;
+    pub const NFC: u8 = 0b100;
+
+    pub fn to_byte(self) -> u8 {
+        let mut b = 0;
+        if self.password {
+            b |= Self::PASSWORD;
+        }
+        if self.keyfile {
+            b |= Self::KEYFILE;
+        }
+        if self.nfc {
+            b |= Self::NFC;
+        }
+        b
+    }
+
+    /// Parses the factors of a `version` header; `NFC` only exists from version 10 and
+    /// only together with `PASSWORD`.
+    pub fn from_byte(b: u8, version: u8) -> Result<Self> {
+        let known = if version >= 10 {
+            Self::PASSWORD | Self::KEYFILE | Self::NFC
+        } else {
+            Self::PASSWORD | Self::KEYFILE
+        };
+        if b & (Self::PASSWORD | Self::KEYFILE) == 0
+            || b & !known != 0
+            || (b & Self::NFC != 0 && b & Self::PASSWORD == 0)
+        {
+            bail!("Unsupported key factors 0x{b:02x}");
+        }
+        Ok(Self {
+            password: b & Self::PASSWORD != 0,
+            keyfile: b & Self::KEYFILE != 0,
+            nfc: b & Self::NFC != 0,
+        })
+    }
+}
+
+/// A DEK encrypted with AES-256-GCM under a KEK.
+#[derive(Debug, Clone)]
+pub(crate) struct WrappedKey {
+    pub nonce: [u8; 12],
+    pub ciphertext: [u8; 48],
+}
+
+impl WrappedKey {
+    pub const LEN: usize = 12 + 48;
+
+    pub fn seal(kek: &[u8; 32], dek: &[u8; 32], aad: &[u8]) -> Result<Self> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(kek));
+        let mut nonce = [0u8; 12];
+        fill(&mut nonce).map_err(|e| anyhow!("OS RNG failed for nonce: {e}"))?;
+        let ciphertext = cipher
+            .encrypt((&nonce).into(), Payload { msg: dek, aad })
+            .map_err(|_| anyhow!("Key wrapping failed"))?;
+        Ok(Self {
+            nonce,
+            ciphertext: ciphertext.try_into().unwrap(),
+        })
+    }
+
+    /// Fails when `kek` is not the key the DEK was wrapped with.
+    pub fn open(&self, kek: &[u8; 32], aad: &[u8]) -> Option<Zeroizing<[u8; 32]>> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(kek));
+        let dek = Zeroizing::new(
+            cipher
+                .decrypt(
+                    (&self.nonce).into(),
+                    Payload {
+                        msg: &self.ciphertext,
+                        aad,
+                    },
+                )
+                .ok()?,
+        );
+        Some(Zeroizing::new(dek.as_slice().try_into().unwrap()))
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.ciphertext);
+    }
+
+    fn read(data: &[u8]) -> Self {
+        Self {
+            nonce: data[..12].try_into().unwrap(),
+            ciphertext: data[12..Self::LEN].try_into().unwrap(),
+        }
+    }
+}
+
+/// Unlocked with a password and/or keyfile through Argon2id.
+#[derive(Debug, Clone)]
+pub(crate) struct PasswordSlot {
+    pub factors: Factors,
+    pub kdf: KdfParams,
+    pub salt: [u8; 16],
+    /// Key-check value, present from version 7.
+    pub check: Option<[u8; 16]>,
+    /// `None` for version 1–3 files, where the derived key encrypts the payload directly.
+    pub wrapped: Option<WrappedKey>,
+}
+
+impl PasswordSlot {
+    const PARAMS_LEN: usize = 1 + 12 + 16;
+
+    /// Wraps `dek` under `kek`, which must have been derived with these parameters.
+    pub fn seal(
+        factors: Factors,
+        kdf: KdfParams,
+        salt: [u8; 16],
+        kek: &[u8; 32],
+        dek: &[u8; 32],
+    ) -> Result<Self> {
+        let mut slot = Self {
+            factors,
+            kdf,
+            salt,
+            check: Some(key_check_value(kek)),
+            wrapped: None,
+        };
+        slot.wrapped = Some(WrappedKey::seal(kek, dek, &slot.wrap_aad())?);
+        Ok(slot)
+    }
+
+    /// Whether `kek` matches the slot's key-check value. Slots without one always match.
+    pub fn check_key(&self, kek: &[u8; 32]) -> bool {
+        match &self.check {
+            Some(check) => key_check_value(kek).ct_eq(check).into(),
+            None => true,
+        }
+    }
+
+    /// Returns the payload key: the unwrapped DEK, or `kek` itself for legacy slots.
+    pub fn open(&self, kek: &[u8; 32]) -> Option<Zeroizing<[u8; 32]>> {
+        match &self.wrapped {
+            Some(wrapped) => wrapped.open(kek, &self.wrap_aad()),
+            None => Some(Zeroizing::new(*kek)),
+        }
+    }
+
+    /// Binds the wrapped key to the parameters it was derived with.
+    fn wrap_aad(&self) -> Vec<u8> {
+        let mut aad = vec![KIND_PASSWORD];
+        self.write_params(&mut aad);
+        aad
+    }
+
+    pub fn write_params(&self, out: &mut Vec<u8>) {
+        out.push(self.factors.to_byte());
+        out.extend_from_slice(&self.kdf.memory_kib.to_le_bytes());
+        out.extend_from_slice(&self.kdf.iterations.to_le_bytes());
+        out.extend_from_slice(&self.kdf.parallelism.to_le_bytes());
+        out.extend_from_slice(&self.salt);
+    }
+
+    /// Reads the `FACTORS | MEMORY_KIB | ITERATIONS | PARALLELISM | SALT` fields.
+    pub fn read_params(data: &[u8], version: u8) -> Result<Self> {
+        if data.len() < Self::PARAMS_LEN {
+            bail!("Key slot too short");
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        Ok(Self {
+            factors: Factors::from_byte(data[0], version)?,
+            kdf: KdfParams {
+                memory_kib: u32_at(1),
+                iterations: u32_at(5),
+                parallelism: u32_at(9),
+            },
+            salt: data[13..29].try_into().unwrap(),
+            check: None,
+            wrapped: None,
+        })
+    }
+
+    fn write_body(&self, out: &mut Vec<u8>) {
+        self.write_params(out);
+        if let Some(check) = &self.check {
+            out.extend_from_slice(check);
+        }
+        self.wrapped
+            .as_ref()
+            .expect("only wrapped slots are written")
+            .write(out);
+    }
+
+    /// Reads `PARAMS | CHECK | WRAPPED`, where `CHECK` is only present from version 7.
+    fn read_body(data: &[u8], version: u8) -> Result<Self> {
+        let check_len = if version >= 7 { 16 } else { 0 };
+        if data.len() != Self::PARAMS_LEN + check_len + WrappedKey::LEN {
+            bail!("Bad password slot length");
+        }
+        let mut slot = Self::read_params(data, version)?;
+        let rest = &data[Self::PARAMS_LEN..];
+        if check_len > 0 {
+            slot.check = Some(rest[..16].try_into().unwrap());
+        }
+        slot.wrapped = Some(WrappedKey::read(&rest[check_len..]));
+        Ok(slot)
+    }
+}
+
+/// Unlocked by combining `threshold` Shamir shares of a random KEK.
+///
+/// Body: `THRESHOLD | COUNT | SHARE_HASHES (32 bytes each) | WRAPPED`. The hashes let a
+/// bad share be named by its index instead of just failing to unwrap.
+#[derive(Debug, Clone)]
+pub(crate) struct SharesSlot {
+    pub threshold: u8,
+    pub hashes: Vec<[u8; 32]>,
+    pub wrapped: WrappedKey,
+}
+
+impl SharesSlot {
+    pub fn seal(
+        threshold: u8,
+        hashes: Vec<[u8; 32]>,
+        kek: &[u8; 32],
+        dek: &[u8; 32],
+    ) -> Result<Self> {
+        let aad = Self::aad(threshold, &hashes);
+        Ok(Self {
+            threshold,
+            wrapped: WrappedKey::seal(kek, dek, &aad)?,
+            hashes,
+        })
+    }
+
+    pub fn count(&self) -> u8 {
+        self.hashes.len() as u8
+    }
+
+    pub fn open(&self, kek: &[u8; 32]) -> Option<Zeroizing<[u8; 32]>> {
+        self.wrapped
+            .open(kek, &Self::aad(self.threshold, &self.hashes))
+    }
+
+    fn aad(threshold: u8, hashes: &[[u8; 32]]) -> Vec<u8> {
+        let mut aad = vec![KIND_SHARES, threshold, hashes.len() as u8];
+        aad.extend(hashes.iter().flatten());
+        aad
+    }
+
+    fn write_body(&self, out: &mut Vec<u8>) {
+        out.push(self.threshold);
+        out.push(self.count());
+        out.extend(self.hashes.iter().flatten());
+        self.wrapped.write(out);
+    }
+
+    fn read_body(data: &[u8]) -> Result<Self> {
+        let count = *data.get(1).ok_or(anyhow!("Key slot too short"))? as usize;
+        if data.len() != 2 + count * 32 + WrappedKey::LEN {
+            bail!("Bad share slot length");
+        }
+        let threshold = data[0];
+        if threshold == 0 || threshold as usize > count {
+            bail!("Bad share threshold {threshold} of {count}");
+        }
+        Ok(Self {
+            threshold,
+            hashes: data[2..2 + count * 32]
+                .chunks_exact(32)
+                .map(|h| h.try_into().unwrap())
+                .collect(),
+            wrapped: WrappedKey::read(&data[2 + count * 32..]),
+        })
+    }
+}
+
+/// Unlocked by a 24-word recovery key (see `recovery`). Body: `WRAPPED`.
+#[derive(Debug, Clone)]
+pub(crate) struct RecoverySlot {
+    pub wrapped: WrappedKey,
+}
+
+impl RecoverySlot {
+    pub fn seal(kek: &[u8; 32], dek: &[u8; 32]) -> Result<Self> {
+        Ok(Self {
+            wrapped: WrappedKey::seal(kek, dek, &[KIND_RECOVERY])?,
+        })
+    }
+
+    pub fn open(&self, kek: &[u8; 32]) -> Option<Zeroizing<[u8; 32]>> {
+        self.wrapped.open(kek, &[KIND_RECOVERY])
+    }
+
+    fn read_body(data: &[u8]) -> Result<Self> {
+        if data.len() != WrappedKey::LEN {
+            bail!("Bad recovery slot length");
+        }
+        Ok(Self {
+            wrapped: WrappedKey::read(data),
+        })
+    }
+}
+
+/// Unlocked by a hybrid recipient's secret key (see `hybrid`).
+///
+/// Body: `EPHEMERAL_X25519 (32) | MLKEM_CIPHERTEXT (1088) | WRAPPED`. The slot does not
+/// say whose it is; decryption tries each given key.
+#[derive(Debug, Clone)]
+pub(crate) struct HybridSlot {
+    pub ephemeral: [u8; 32],
+    pub ciphertext: Box<[u8; CT_LEN]>,
+    pub wrapped: WrappedKey,
+}
+
+impl HybridSlot {
+    pub fn seal(recipient: &HybridPublicKey, dek: &[u8; 32]) -> Result<Self> {
+        let sealed = hybrid::encapsulate(recipient)?;
+        let aad = Self::aad(&sealed.ephemeral, &sealed.ciphertext);
+        Ok(Self {
+            ephemeral: sealed.ephemeral,
+            ciphertext: Box::new(sealed.ciphertext),
+            wrapped: WrappedKey::seal(&sealed.kek, dek, &aad)?,
+        })
+    }
+
+    pub fn open(&self, identity: &HybridSecretKey) -> Option<Zeroizing<[u8; 32]>> {
+        let kek = hybrid::decapsulate(identity, &self.ephemeral, &self.ciphertext);
+        self.wrapped
+            .open(&kek, &Self::aad(&self.ephemeral, &self.ciphertext))
+    }
+
+    fn aad(ephemeral: &[u8; 32], ciphertext: &[u8; CT_LEN]) -> Vec<u8> {
+        let mut aad = vec![KIND_HYBRID];
+        aad.extend_from_slice(ephemeral);
+        aad.extend_from_slice(ciphertext);
+        aad
+    }
+
+    fn write_body(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.ephemeral);
+        out.extend_from_slice(self.ciphertext.as_ref());
+        self.wrapped.write(out);
+    }
+
+    fn read_body(data: &[u8]) -> Result<Self> {
+        if data.len() != 32 + CT_LEN + WrappedKey::LEN {
+            bail!("Bad recipient slot length");
+        }
+        Ok(Self {
+            ephemeral: data[..32].try_into().unwrap(),
+            ciphertext: Box::new(data[32..32 + CT_LEN].try_into().unwrap()),
+            wrapped: WrappedKey::read(&data[32 + CT_LEN..]),
+        })
+    }
+}
+
+/// The DEK wrapped for an OpenSSH key: `TAG | ENCAPSULATED | WRAPPED`, where the
+/// encapsulated key is an X25519 ephemeral key or an RSA-OAEP ciphertext.
+#[derive(Debug, Clone)]
+pub(crate) struct SshSlot {
+    pub tag: [u8; TAG_LEN],
+    pub encapsulated: Vec<u8>,
+    pub wrapped: WrappedKey,
+}
+
+impl SshSlot {
+    pub fn seal(recipient: &ssh_key::PublicKey, dek: &[u8; 32]) -> Result<Self> {
+        let (kek, encapsulated) = ssh::encapsulate(recipient)?;
+        let tag = ssh::tag(recipient);
+        let aad = Self::aad(&tag, &encapsulated);
+        Ok(Self {
+            tag,
+            encapsulated,
+            wrapped: WrappedKey::seal(&kek, dek, &aad)?,
+        })
+    }
+
+    /// `None` for slots made for other keys, without trying them.
+    pub fn open(&self, identity: &ssh_key::PrivateKey) -> Option<Zeroizing<[u8; 32]>> {
+        if ssh::tag(identity.public_key()) != self.tag {
+            return None;
+        }
+        let kek = ssh::decapsulate(identity, &self.encapsulated)?;
+        self.wrapped
+            .open(&kek, &Self::aad(&self.tag, &self.encapsulated))
+    }
+
+    fn aad(tag: &[u8; TAG_LEN], encapsulated: &[u8]) -> Vec<u8> {
+        let mut aad = vec![KIND_SSH];
+        aad.extend_from_slice(tag);
+        aad.extend_from_slice(encapsulated);
+        aad
+    }
+
+    fn write_body(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.tag);
+        out.extend_from_slice(&self.encapsulated);
+        self.wrapped.write(out);
+    }
+
+    fn read_body(data: &[u8]) -> Result<Self> {
+        let Some(end) = data
+            .len()
+            .checked_sub(WrappedKey::LEN)
+            .filter(|&n| n > TAG_LEN)
+        else {
+            bail!("Bad SSH recipient slot length");
+        };
+        Ok(Self {
+            tag: data[..TAG_LEN].try_into().unwrap(),
+            encapsulated: data[TAG_LEN..end].to_vec(),
+            wrapped: WrappedKey::read(&data[end..]),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum KeySlot {
+    Password(PasswordSlot),
+    Shares(SharesSlot),
+    Recovery(RecoverySlot),
+    Hybrid(HybridSlot),
+    Ssh(SshSlot),
+    /// A slot kind this version does not know. It is kept so the header can be
+    /// rewritten without losing it, but cannot be used to unlock.
+    Unknown {
+        kind: u8,
+        body: Vec<u8>,
+    },
+}
+
+impl KeySlot {
+    /// Writes `KIND | LEN (u16 LE) | BODY`.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        let kind = match self {
+            KeySlot::Password(slot) => {
+                slot.write_body(&mut body);
+                KIND_PASSWORD
+            }
+            KeySlot::Shares(slot) => {
+                slot.write_body(&mut body);
+                KIND_SHARES
+            }
+            KeySlot::Recovery(slot) => {
+                slot.wrapped.write(&mut body);
+                KIND_RECOVERY
+            }
+            KeySlot::Hybrid(slot) => {
+                slot.write_body(&mut body);
+                KIND_HYBRID
+            }
+            KeySlot::Ssh(slot) => {
+                slot.write_body(&mut body);
+                KIND_SSH
+            }
+            KeySlot::Unknown { kind, body: raw } => {
+                body.extend_from_slice(raw);
+                *kind
+            }
+        };
+        out.push(kind);
+        out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+        out.extend_from_slice(&body);
+    }
+
+    /// Reads one slot of a `version` header from the start of `data` and returns it with
+    /// its encoded length.
+    pub fn read(data: &[u8], version: u8) -> Result<(Self, usize)> {
+        if data.len() < 3 {
+            bail!("Key slot too short");
+        }
+        let kind = data[0];
+        let len = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let body = data.get(3..3 + len).ok_or(anyhow!("Key slot too short"))?;
+        let slot = match kind {
+            KIND_PASSWORD => KeySlot::Password(PasswordSlot::read_body(body, version)?),
+            KIND_SHARES => KeySlot::Shares(SharesSlot::read_body(body)?),
+            KIND_RECOVERY => KeySlot::Recovery(RecoverySlot::read_body(body)?),
+            KIND_HYBRID => KeySlot::Hybrid(HybridSlot::read_body(body)?),
+            KIND_SSH => KeySlot::Ssh(SshSlot::read_body(body)?),
+            _ => KeySlot::Unknown {
+                kind,
+                body: body.to_vec(),
+            },
+        };
+        Ok((slot, 3 + len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_slot_roundtrip() {
+        let factors = Factors {
+            password: true,
+            keyfile: true,
+            nfc: false,
+        };
+        let kek = [1u8; 32];
+        let dek = [2u8; 32];
+        let slot = PasswordSlot::seal(factors, KdfParams::default(), [3; 16], &kek, &dek).unwrap();
+
+        let mut bytes = Vec::new();
+        KeySlot::Password(slot).write(&mut bytes);
+        let (parsed, len) = KeySlot::read(&bytes, 7).unwrap();
+        assert_eq!(len, bytes.len());
+        let KeySlot::Password(parsed) = parsed else {
+            panic!("expected a password slot");
+        };
+        assert_eq!(parsed.factors, factors);
+        assert!(parsed.check_key(&kek));
+        assert!(!parsed.check_key(&[9u8; 32]));
+        assert_eq!(*parsed.open(&kek).unwrap(), dek);
+        assert!(parsed.open(&[9u8; 32]).is_none());
+    }
+
+    #[test]
+    fn tampered_slot_params_fail_to_open() {
+        let kek = [1u8; 32];
+        let mut slot = PasswordSlot::seal(
+            Factors {
+                password: true,
+                keyfile: false,
+                nfc: false,
+            },
+            KdfParams::default(),
+            [3; 16],
+            &kek,
+            &[2u8; 32],
+        )
+        .unwrap();
+        slot.kdf.iterations += 1;
+        assert!(slot.open(&kek).is_none());
+    }
+
+    #[test]
+    fn shares_slot_roundtrip() {
+        let slot =
+            SharesSlot::seal(2, vec![[4; 32], [5; 32], [6; 32]], &[1; 32], &[2; 32]).unwrap();
+        let mut bytes = Vec::new();
+        KeySlot::Shares(slot).write(&mut bytes);
+        let (KeySlot::Shares(parsed), len) = KeySlot::read(&bytes, 9).unwrap() else {
+            panic!("expected a share slot");
+        };
+        assert_eq!(len, bytes.len());
+        assert_eq!((parsed.threshold, parsed.count()), (2, 3));
+        assert_eq!(*parsed.open(&[1; 32]).unwrap(), [2; 32]);
+
+        // The hashes are bound to the wrapped key
+        let mut swapped = parsed.clone();
+        swapped.hashes.swap(0, 1);
+        assert!(swapped.open(&[1; 32]).is_none());
+    }
+
+    #[test]
+    fn hybrid_slot_roundtrip() {
+        let identity = hybrid::generate_key().unwrap();
+        let slot = HybridSlot::seal(&identity.public_key(), &[2; 32]).unwrap();
+        let mut bytes = Vec::new();
+        KeySlot::Hybrid(slot).write(&mut bytes);
+        let (KeySlot::Hybrid(parsed), len) = KeySlot::read(&bytes, 9).unwrap() else {
+            panic!("expected a recipient slot");
+        };
+        assert_eq!(len, bytes.len());
+        assert_eq!(*parsed.open(&identity).unwrap(), [2; 32]);
+        assert!(parsed.open(&hybrid::generate_key().unwrap()).is_none());
+
+        let mut tampered = parsed.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert!(tampered.open(&identity).is_none());
+    }
+
+    #[test]
+    fn ssh_slot_roundtrip() {
+        use rand_core::OsRng;
+        use ssh_key::{Algorithm, PrivateKey};
+
+        let identity = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let slot = SshSlot::seal(identity.public_key(), &[2; 32]).unwrap();
+        let mut bytes = Vec::new();
+        KeySlot::Ssh(slot).write(&mut bytes);
+        let (KeySlot::Ssh(parsed), len) = KeySlot::read(&bytes, 9).unwrap() else {
+            panic!("expected an SSH recipient slot");
+        };
+        assert_eq!(len, bytes.len());
+        assert_eq!(*parsed.open(&identity).unwrap(), [2; 32]);
+        let other = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        assert!(parsed.open(&other).is_none());
+
+        let mut tampered = parsed.clone();
+        tampered.encapsulated[0] ^= 1;
+        assert!(tampered.open(&identity).is_none());
+    }
+
+    #[test]
+    fn unknown_slot_kinds_are_kept() {
+        let bytes = [0x7f, 2, 0, 0xaa, 0xbb];
+        let (slot, len) = KeySlot::read(&bytes, 7).unwrap();
+        assert_eq!(len, 5);
+        let mut out = Vec::new();
+        slot.write(&mut out);
+        assert_eq!(out, bytes);
+    }
+
+    #[test]
+    fn version6_slots_have_no_key_check() {
+        let kek = [1u8; 32];
+        let mut slot = PasswordSlot::seal(
+            Factors {
+                password: true,
+                keyfile: false,
+                nfc: false,
+            },
+            KdfParams::default(),
+            [3; 16],
+            &kek,
+            &[2u8; 32],
+        )
+        .unwrap();
+        slot.check = None;
+        let mut bytes = Vec::new();
+        KeySlot::Password(slot).write(&mut bytes);
+
+        let (KeySlot::Password(parsed), _) = KeySlot::read(&bytes, 6).unwrap() else {
+            panic!("expected a password slot");
+        };
+        assert!(parsed.check.is_none());
+        assert!(parsed.check_key(&[9u8; 32]));
+        assert_eq!(*parsed.open(&kek).unwrap(), [2u8; 32]);
+        assert!(KeySlot::read(&bytes, 7).is_err());
+    }
+}
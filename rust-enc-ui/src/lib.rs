@@ -1,98 +1,875 @@
-use aes_gcm::{
-    Aes256Gcm, KeyInit,
-    aead::{Aead, Key},
-};
 use anyhow::{Context, Result, anyhow};
-use argon2::Argon2;
 use getrandom::fill;
-use std::{fs, io::Write, path::PathBuf};
-use zeroize::Zeroize;
+use std::{fs, io::Write, path::PathBuf, time::SystemTime};
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, Zeroizing};
+
+mod checksum;
+mod error;
+mod fingerprint;
+mod header;
+pub mod hybrid;
+mod inspect;
+mod kdf;
+pub mod keyring;
+mod keyslot;
+mod mlkem;
+mod padding;
+pub mod paper;
+mod parity;
+pub mod passphrase;
+pub mod password_source;
+pub mod recipient;
+pub mod recovery;
+mod shamir;
+pub mod signing;
+mod ssh;
+pub mod strength;
+mod suite;
+mod volume;
+
+pub use checksum::CheckReport;
+pub use error::Error;
+pub use inspect::{FileInfo, format_utc};
+pub use kdf::{KdfLimits, KdfParams};
+pub use padding::{MAX_PADDING_BUCKET, Padding};
+pub use parity::RepairReport;
+pub use recipient::{Identity, Recipient};
+pub use shamir::ShareSplit;
+pub use signing::Signer;
+pub use suite::Suite;
+
+use checksum::Trailer;
+use header::{
+    EXT_COMMENT, EXT_EXPIRY, EXT_PAYLOAD_LEN, Extension, FLAG_CHECKSUM, FLAG_PADDED, FLAG_PARITY,
+    FLAG_SIGNED, Header, MAGIC, VERSION,
+};
+use kdf::{derive_key, hash_keyfile, password_input, secret_input, split_data_key};
+use keyslot::{Factors, HybridSlot, KeySlot, PasswordSlot, RecoverySlot, SharesSlot, SshSlot};
+use shamir::Share;
+use signing::{SigningKey, VerifyingKey};
+
+/// Settings for `run_encrypt_with`.
+#[derive(Debug, Clone, Default)]
+pub struct EncryptOptions {
+    pub kdf: KdfParams,
+    pub suite: Suite,
+    /// Pads the plaintext before encryption so the file size hides its exact length.
+    pub padding: Padding,
+    /// Mixed into the key together with the password. With an empty password the
+    /// keyfile alone protects the file.
+    pub keyfile: Option<PathBuf>,
+    /// Signs the plaintext and header; the signature is encrypted along with the data.
+    pub signing_key: Option<SigningKey>,
+    /// Appends chunk hashes of the file so `run_check` can find corruption without a key.
+    pub checksum: bool,
+    /// Reed-Solomon parity shards per 32 checksum chunks, so that many damaged chunks in
+    /// each group can be rebuilt (4 adds 12.5%). Zero disables parity; any other value
+    /// implies `checksum`.
+    pub parity: u8,
+    /// Splits the output into `OUTPUT.001`, `OUTPUT.002`, … of at most this many bytes
+    /// each. Decryption accepts any volume of the set.
+    pub volume_size: Option<u64>,
+    /// Writes the header to this file instead, leaving the output as bare ciphertext.
+    /// The payload cannot be decrypted without it.
+    pub detached_header: Option<PathBuf>,
+    /// Stored unencrypted in the header but authenticated, so anyone can read it with
+    /// `run_inspect` and nobody can change it unnoticed. At most `MAX_COMMENT_LEN` bytes.
+    pub comment: Option<String>,
+    /// Decryption is refused after this time unless explicitly allowed. This is a policy
+    /// the tool enforces, not a cryptographic guarantee.
+    pub expires: Option<SystemTime>,
+    /// Splits a key for the file into Shamir shares, returned in `EncryptReport::shares`.
+    /// With an empty password and no keyfile the shares are the only way in.
+    pub shares: Option<ShareSplit>,
+    /// Adds a recovery key slot; the 24 words are returned once in
+    /// `EncryptReport::recovery_words` and cannot be recovered from the file.
+    pub recovery_key: bool,
+    /// Public keys that can decrypt the file with their secret key: RENC hybrid keys or
+    /// `ssh-ed25519` and `ssh-rsa` keys. With an empty password and no keyfile, no
+    /// password slot is added.
+    pub recipients: Vec<Recipient>,
+}
+
+/// What `run_encrypt_with` produced besides the output file.
+#[derive(Debug, Clone, Default)]
+pub struct EncryptReport {
+    /// Printable shares when `EncryptOptions::shares` was set, one per holder.
+    pub shares: Vec<Zeroizing<String>>,
+    /// The recovery key when `EncryptOptions::recovery_key` was set.
+    pub recovery_words: Option<Zeroizing<String>>,
+}
+
+/// Longest `EncryptOptions::comment`, in bytes.
+pub const MAX_COMMENT_LEN: usize = 4096;
+
+/// Settings for `run_decrypt_with`.
+#[derive(Debug, Clone, Default)]
+pub struct DecryptOptions {
+    pub kdf_limits: KdfLimits,
+    /// Required when the file was encrypted with a keyfile, ignored otherwise.
+    pub keyfile: Option<PathBuf>,
+    /// When non-empty, only files signed by one of these keys are decrypted.
+    pub trusted_signers: Vec<VerifyingKey>,
+    /// Header file for a payload encrypted with `EncryptOptions::detached_header`.
+    pub detached_header: Option<PathBuf>,
+    /// Decrypts files past their `EncryptOptions::expires` time.
+    pub allow_expired: bool,
+    /// Shares to combine instead of using the password; at least the threshold of them.
+    pub shares: Vec<String>,
+    /// Recovery words to use instead of the password.
+    pub recovery_words: Option<String>,
+    /// Secret keys to try first, for files encrypted to recipients. The password and
+    /// keyfile are used when none of them opens a slot.
+    pub identities: Vec<Identity>,
+}
 
-const MAGIC: &[u8; 4] = b"RENC";
-const VERSION: u8 = 1;
+/// What `run_decrypt_with` learned about the file.
+#[derive(Debug, Clone, Default)]
+pub struct DecryptReport {
+    /// Set for signed files; the signature has already been verified.
+    pub signer: Option<Signer>,
+    /// Set for files with parity; damage listed here was repaired in memory before
+    /// decryption.
+    pub repair: Option<RepairReport>,
+    /// The header comment, verified along with the payload.
+    pub comment: Option<String>,
+    /// The checksum trailer or parity section could not be read and was skipped; the
+    /// AEAD tag still authenticated the whole payload.
+    pub integrity_skipped: bool,
+}
 
 pub fn run_encrypt(input: Option<PathBuf>, output: Option<PathBuf>, password: &str) -> Result<()> {
+    run_encrypt_with(input, output, password, &EncryptOptions::default()).map(|_| ())
+}
+
+pub fn run_encrypt_with(
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    password: &str,
+    opts: &EncryptOptions,
+) -> Result<EncryptReport> {
     let in_path = input.context("No input file selected")?;
     let out_path = output.context("No output file selected")?;
+    if opts
+        .comment
+        .as_ref()
+        .is_some_and(|c| c.len() > MAX_COMMENT_LEN)
+    {
+        anyhow::bail!("Comment is longer than {MAX_COMMENT_LEN} bytes");
+    }
+    opts.padding.check()?;
+    let other_ways_in = opts.shares.is_some() || !opts.recipients.is_empty();
+    let password_slot = !other_ways_in || !password.is_empty() || opts.keyfile.is_some();
+    let slot_count = usize::from(password_slot)
+        + usize::from(opts.shares.is_some())
+        + opts.recipients.len()
+        + usize::from(opts.recovery_key);
+    if slot_count > usize::from(u8::MAX) {
+        anyhow::bail!(
+            "A file holds at most {} key slots; {slot_count} were requested",
+            u8::MAX
+        );
+    }
     let mut plaintext =
         fs::read(&in_path).with_context(|| format!("Reading {}", in_path.display()))?;
 
-    // Derive key
-    let mut salt = [0u8; 16];
-    fill(&mut salt).map_err(|e| anyhow!("OS RNG failed for salt: {e}"))?;
-    let mut key = derive_key(password, &salt)?;
+    // Random data-encryption key, wrapped by the password-derived key, shares, recipient
+    // keys or a recovery key
+    let mut dek = Zeroizing::new([0u8; 32]);
+    fill(dek.as_mut()).map_err(|e| anyhow!("OS RNG failed for data key: {e}"))?;
+    let mut slots = Vec::new();
+    let mut report = EncryptReport::default();
+    if password_slot {
+        slots.push(KeySlot::Password(seal_password_slot(
+            password, opts, &dek, VERSION,
+        )?));
+    }
+    if let Some(how) = opts.shares {
+        let mut kek = Zeroizing::new([0u8; 32]);
+        fill(kek.as_mut()).map_err(|e| anyhow!("OS RNG failed for share key: {e}"))?;
+        let shares = shamir::split(&kek, how)?;
+        let hashes = shares.iter().map(Share::hash).collect();
+        slots.push(KeySlot::Shares(SharesSlot::seal(
+            how.threshold,
+            hashes,
+            &kek,
+            &dek,
+        )?));
+        report.shares = shares.iter().map(Share::to_text).collect();
+    }
+    for recipient in &opts.recipients {
+        slots.push(match recipient {
+            Recipient::Hybrid(key) => KeySlot::Hybrid(HybridSlot::seal(key, &dek)?),
+            Recipient::Ssh(key) => KeySlot::Ssh(SshSlot::seal(key, &dek)?),
+        });
+    }
+    if opts.recovery_key {
+        let (secret, words) = recovery::generate()?;
+        let kek = recovery::derive_kek(&secret);
+        slots.push(KeySlot::Recovery(RecoverySlot::seal(&kek, &dek)?));
+        report.recovery_words = Some(words);
+    }
+    let (payload_key, commitment) = split_data_key(&dek);
+
+    let mut flags = 0;
+    if opts.padding != Padding::None {
+        flags |= FLAG_PADDED;
+    }
+    if opts.signing_key.is_some() {
+        flags |= FLAG_SIGNED;
+    }
+    if opts.checksum || opts.parity > 0 {
+        flags |= FLAG_CHECKSUM;
+    }
+    if opts.parity > 0 {
+        flags |= FLAG_PARITY;
+    }
 
-    // AEAD
-    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
     let mut nonce = [0u8; 12];
     fill(&mut nonce).map_err(|e| anyhow!("OS RNG failed for nonce: {e}"))?;
+    let mut header = Header::new(opts.suite, flags, nonce, commitment, slots);
+    if let Some(comment) = &opts.comment {
+        header.extensions.push(Extension {
+            kind: EXT_COMMENT,
+            value: comment.as_bytes().to_vec(),
+        });
+    }
+    if let Some(time) = opts.expires {
+        header.extensions.push(Extension {
+            kind: EXT_EXPIRY,
+            value: inspect::expiry_value(time),
+        });
+    }
+    if flags & FLAG_CHECKSUM != 0 {
+        // The payload's length is fixed by now, and recording it lets decryption find the
+        // ciphertext without trusting the integrity data that follows it
+        let mut len = plaintext.len();
+        if opts.signing_key.is_some() {
+            len += signing::BLOCK_LEN;
+        }
+        if opts.padding != Padding::None {
+            len = opts.padding.padded_len(len as u64) as usize;
+        }
+        header.extensions.push(Extension {
+            kind: EXT_PAYLOAD_LEN,
+            value: ((len + suite::TAG_LEN) as u64).to_le_bytes().to_vec(),
+        });
+    }
+    let header_bytes = header.to_bytes()?;
+    let aad = header.aad(&header_bytes);
+
+    // Sign-then-encrypt: the signature block travels inside the payload
+    if let Some(key) = &opts.signing_key {
+        let mut signed = Vec::with_capacity(plaintext.len() + signing::BLOCK_LEN);
+        signed.extend_from_slice(&plaintext);
+        signed.extend_from_slice(&signing::sign(key, aad, &plaintext));
+        plaintext.zeroize();
+        plaintext = signed;
+    }
+
+    // Optional length hiding
+    if opts.padding != Padding::None {
+        let padded = padding::pad(&plaintext, opts.padding);
+        plaintext.zeroize();
+        plaintext = padded;
+    }
 
-    let ciphertext = cipher
-        .encrypt((&nonce).into(), plaintext.as_ref())
-        .map_err(|_| anyhow!("Encryption failed"))?;
+    // AEAD
+    let ciphertext = opts.suite.encrypt(&payload_key, &nonce, &plaintext, aad)?;
 
-    // Write: MAGIC|VERSION|SALT|NONCE|CIPHERTEXT
-    let mut out = Vec::with_capacity(4 + 1 + 16 + 12 + ciphertext.len());
-    out.extend_from_slice(MAGIC);
-    out.push(VERSION);
-    out.extend_from_slice(&salt);
-    out.extend_from_slice(&nonce);
+    // Write: HEADER|CIPHERTEXT, or HEADER and CIPHERTEXT to separate files
+    let mut out = Vec::with_capacity(header_bytes.len() + ciphertext.len());
+    if let Some(path) = &opts.detached_header {
+        fs::write(path, &header_bytes).with_context(|| format!("Writing {}", path.display()))?;
+    } else {
+        out.extend_from_slice(&header_bytes);
+    }
     out.extend_from_slice(&ciphertext);
+    append_integrity(&mut out, flags, opts.parity)?;
 
-    let mut f =
-        fs::File::create(&out_path).with_context(|| format!("Creating {}", out_path.display()))?;
-    f.write_all(&out)?;
-    f.flush()?;
+    if let Some(size) = opts.volume_size {
+        volume::write(&out_path, &out, size)?;
+    } else {
+        let mut f = fs::File::create(&out_path)
+            .with_context(|| format!("Creating {}", out_path.display()))?;
+        f.write_all(&out)?;
+        f.flush()?;
+    }
 
     // Wipe sensitive material
     plaintext.zeroize();
-    key.zeroize();
 
-    Ok(())
+    Ok(report)
 }
 
 pub fn run_decrypt(input: Option<PathBuf>, output: Option<PathBuf>, password: &str) -> Result<()> {
+    run_decrypt_with(input, output, password, &DecryptOptions::default()).map(|_| ())
+}
+
+pub fn run_decrypt_with(
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    password: &str,
+    opts: &DecryptOptions,
+) -> Result<DecryptReport> {
     let in_path = input.context("No input file selected")?;
     let out_path = output.context("No output file selected")?;
-    let data = fs::read(&in_path).with_context(|| format!("Reading {}", in_path.display()))?;
+    let mut data = volume::read(&in_path)?;
+
+    // Rebuild damaged chunks before anything is parsed or authenticated
+    let repair = parity::repair(&mut data)?;
+    let data = &data[..parity::section_start(&data)];
 
-    // Parse header
-    if data.len() < 4 + 1 + 16 + 12 {
-        anyhow::bail!("File too short");
+    // Parse header, from the payload itself or from a detached header file
+    let detached = match &opts.detached_header {
+        Some(path) => Some(fs::read(path).with_context(|| format!("Reading {}", path.display()))?),
+        None if !data.starts_with(MAGIC) => anyhow::bail!(
+            "Not an encrypted file; a payload with a detached header needs its .rhdr file"
+        ),
+        None => None,
+    };
+    let (header, header_len) = Header::parse(detached.as_deref().unwrap_or(data))?;
+    let (header_bytes, body_start) = match &detached {
+        Some(bytes) if header_len != bytes.len() => anyhow::bail!("Not a detached header file"),
+        Some(bytes) => (&bytes[..], 0),
+        None => (&data[..header_len], header_len),
+    };
+    // An unreadable parity section is skipped when the header says where the ciphertext
+    // ends; older files need it to tell the ciphertext from the integrity data
+    let parity_skipped = header.flags & FLAG_PARITY != 0 && repair.is_none();
+    if parity_skipped && inspect::payload_len(&header)?.is_none() {
+        anyhow::bail!("Parity section is damaged");
     }
-    if &data[0..4] != MAGIC {
-        anyhow::bail!("Bad magic");
+    if let Some(at) = inspect::expiry(&header)?
+        && at <= SystemTime::now()
+        && !opts.allow_expired
+    {
+        return Err(Error::Expired { at }.into());
     }
-    if data[4] != VERSION {
-        anyhow::bail!("Unsupported version");
+    let aad = header.aad(header_bytes);
+    let (payload_end, trailer_skipped) = payload_end(&header, data, body_start)?;
+    let ciphertext = &data[body_start..payload_end];
+    if ciphertext.len() < suite::TAG_LEN {
+        anyhow::bail!("Ciphertext too short (missing authentication tag)");
     }
 
-    let salt = &data[5..21];
-    let nonce = &data[21..33];
-    let ciphertext = &data[33..];
+    let (dek, _) = unlock(&header, password, opts)?;
+    let key = payload_key(&header, &dek);
+    let mut plaintext =
+        Zeroizing::new(header.suite.decrypt(&key, &header.nonce, ciphertext, aad)?);
+    if header.flags & FLAG_PADDED != 0 {
+        padding::unpad(&mut plaintext)?;
+    }
 
-    let mut key = derive_key(password, salt)?;
-    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let signer = if header.flags & FLAG_SIGNED != 0 {
+        let split = plaintext
+            .len()
+            .checked_sub(signing::BLOCK_LEN)
+            .ok_or(Error::BadSignature)?;
+        let block = plaintext[split..].try_into().unwrap();
+        let public_key = signing::verify(block, aad, &plaintext[..split])?;
+        plaintext.truncate(split);
+        Some(Signer {
+            fingerprint: signing::fingerprint(&public_key),
+            trusted: opts.trusted_signers.contains(&public_key),
+            public_key,
+        })
+    } else {
+        None
+    };
+    if !opts.trusted_signers.is_empty() {
+        match &signer {
+            None => return Err(Error::SignatureRequired.into()),
+            Some(s) if !s.trusted => {
+                return Err(Error::UntrustedSigner {
+                    fingerprint: s.fingerprint.clone(),
+                }
+                .into());
+            }
+            Some(_) => {}
+        }
+    }
 
-    let mut plaintext = cipher
-        .decrypt(nonce.into(), ciphertext)
-        .map_err(|_| anyhow!("Decryption failed (wrong password or corrupted file)"))?;
+    fs::write(&out_path, plaintext.as_slice())
+        .with_context(|| format!("Writing {}", out_path.display()))?;
 
-    fs::write(&out_path, &plaintext).with_context(|| format!("Writing {}", out_path.display()))?;
+    Ok(DecryptReport {
+        signer,
+        repair,
+        comment: inspect::comment(&header)?,
+        integrity_skipped: trailer_skipped || parity_skipped,
+    })
+}
 
-    plaintext.zeroize();
-    key.zeroize();
+/// Replaces the password slot that `old_password` opens with one for `new_password`.
+/// With `DecryptOptions::shares`, `recovery_words` or `identities` the file's password slot
+/// is replaced, or one is added.
+///
+/// Only the header is rewritten; the payload stays encrypted under the same data key.
+/// `path` may also be a detached header file, in which case the payload is not needed.
+pub fn run_change_password(
+    path: Option<PathBuf>,
+    old_password: &str,
+    new_password: &str,
+    unlock_opts: &DecryptOptions,
+    relock_opts: &EncryptOptions,
+) -> Result<()> {
+    let path = path.context("No input file selected")?;
+    let data = fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+    if volume::is_volume(&data) {
+        anyhow::bail!("Cannot change the password of a split file; decrypt and re-encrypt it");
+    }
+    let (mut header, header_len) = Header::parse(&data)?;
+    if header.version < 4 {
+        anyhow::bail!(
+            "Version {} files have no data key; decrypt and re-encrypt them instead",
+            header.version
+        );
+    }
+
+    let (dek, index) = unlock(&header, old_password, unlock_opts)?;
+    // The file keeps its version, and with it how the password is fed to the KDF
+    let mut slot = seal_password_slot(new_password, relock_opts, &dek, header.version)?;
+    if header.version < 7 {
+        // The version byte is payload AAD, so the file keeps its original slot layout
+        slot.check = None;
+    }
+    // Unlocking another way sets a password and keeps that slot
+    let index = match header.slots[index] {
+        KeySlot::Password(_) => Some(index),
+        _ => header
+            .slots
+            .iter()
+            .position(|s| matches!(s, KeySlot::Password(_))),
+    };
+    match index {
+        Some(index) => header.slots[index] = KeySlot::Password(slot),
+        None => header.slots.push(KeySlot::Password(slot)),
+    }
+
+    let mut out = header.to_bytes()?;
+    if header_len < data.len() {
+        let parity_shards = parity::find(&data).map_or(0, |(layout, _)| layout.parity_shards);
+        let body = &data[..parity::section_start(&data)];
+        let (end, skipped) = payload_end(&header, body, header_len)?;
+        if skipped {
+            // A fresh trailer would vouch for a ciphertext nothing has checked
+            anyhow::bail!("Checksum trailer is damaged; decrypt the file to check it first");
+        }
+        out.extend_from_slice(&body[header_len..end]);
+        append_integrity(&mut out, header.flags, parity_shards)?;
+    }
+    // Write next to the file and rename, so a crash cannot leave it half-written
+    let tmp_path = path.with_extension("renc-tmp");
+    fs::write(&tmp_path, &out).with_context(|| format!("Writing {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("Replacing {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Reads what the header says about a file without the password.
+///
+/// Works on whole files, any volume of a set, and detached header files. Nothing it
+/// returns is verified until the file is decrypted.
+pub fn run_inspect(input: Option<PathBuf>) -> Result<FileInfo> {
+    let in_path = input.context("No input file selected")?;
+    let data = volume::read(&in_path)?;
+    if !data.starts_with(MAGIC) {
+        anyhow::bail!("No header; a payload with a detached header needs its .rhdr file");
+    }
+    let (header, _) = Header::parse(&data)?;
+    FileInfo::from_header(&header)
+}
 
+/// Checks the file against its checksum trailer without decrypting it.
+///
+/// Needs no password. Fails if the file was written without `EncryptOptions::checksum` or
+/// the trailer itself is damaged; otherwise the report lists the damaged byte ranges.
+pub fn run_check(input: Option<PathBuf>) -> Result<CheckReport> {
+    let in_path = input.context("No input file selected")?;
+    let data = volume::read(&in_path)?;
+
+    // The trailer verifies itself, so a damaged header does not hide it
+    let body = &data[..parity::section_start(&data)];
+    match Trailer::read(body) {
+        Ok((trailer, covered)) => {
+            let mut report = trailer.check(&body[..covered]);
+            report.damaged.extend(parity::damaged_shards(&data));
+            Ok(report)
+        }
+        Err(e) => match Header::parse(&data) {
+            Ok((header, _)) if header.flags & FLAG_CHECKSUM == 0 => {
+                anyhow::bail!("File has no checksum trailer")
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+/// Rebuilds damaged parts of a file with parity in place, without the password.
+///
+/// The file is only rewritten when something was repaired; the report says how much
+/// damage was found and what could not be fixed.
+pub fn run_repair(input: Option<PathBuf>) -> Result<RepairReport> {
+    let path = input.context("No input file selected")?;
+    let mut data = fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+    if volume::is_volume(&data) {
+        anyhow::bail!("Cannot repair a split file in place; decryption repairs it in memory");
+    }
+    let Some(report) = parity::repair(&mut data)? else {
+        match Header::parse(&data) {
+            Ok((header, _)) if header.flags & FLAG_PARITY == 0 => {
+                anyhow::bail!("File has no parity data")
+            }
+            _ => anyhow::bail!("Parity section is damaged"),
+        }
+    };
+
+    if report.repaired_bytes() > 0 {
+        let tmp_path = path.with_extension("renc-tmp");
+        fs::write(&tmp_path, &data).with_context(|| format!("Writing {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path).with_context(|| format!("Replacing {}", path.display()))?;
+    }
+    Ok(report)
+}
+
+/// Appends the checksum trailer and parity section that `flags` ask for.
+fn append_integrity(out: &mut Vec<u8>, flags: u16, parity_shards: u8) -> Result<()> {
+    if flags & FLAG_CHECKSUM != 0 {
+        Trailer::compute(out, checksum::CHUNK_LEN).write(out);
+    }
+    if flags & FLAG_PARITY != 0 {
+        let section = parity::encode(out, checksum::CHUNK_LEN, parity_shards)?;
+        out.extend_from_slice(&section);
+    }
     Ok(())
 }
 
-fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
-    let argon = Argon2::default();
-    let mut key = [0u8; 32];
+/// End of the ciphertext in `data`, and whether the checksum trailer had to be skipped.
+///
+/// Damage the trailer locates is refused here, naming the first bad range. A trailer that
+/// cannot be read only costs that early report: the ciphertext then ends where the header
+/// says, or for older files where a trailer for a file of this size would begin, and the
+/// AEAD tag still authenticates it.
+fn payload_end(header: &Header, data: &[u8], body_start: usize) -> Result<(usize, bool)> {
+    let recorded = inspect::payload_len(header)?
+        .map(|len| {
+            usize::try_from(len)
+                .ok()
+                .and_then(|len| body_start.checked_add(len))
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| anyhow!("Payload length extension is out of range"))
+        })
+        .transpose()?;
+    if header.flags & FLAG_CHECKSUM == 0 {
+        return Ok((recorded.unwrap_or(data.len()), false));
+    }
+    let trailer = Trailer::read(data)
+        .ok()
+        .filter(|(_, covered)| recorded.is_none_or(|end| end == *covered));
+    let Some((trailer, covered)) = trailer else {
+        let end = recorded
+            .or_else(|| Trailer::covered_len(data.len()))
+            .filter(|&end| end >= body_start)
+            .context("Checksum trailer is damaged")?;
+        return Ok((end, true));
+    };
+    if let Some(range) = trailer.check(&data[..covered]).damaged.first() {
+        return Err(Error::Corrupted {
+            start: range.start,
+            end: range.end,
+        }
+        .into());
+    }
+    Ok((covered, false))
+}
+
+/// Derives a key-encryption key from the password and/or keyfile and wraps `dek` under it,
+/// for a header of `version`. Passwords are normalized to NFC from version 10.
+fn seal_password_slot(
+    password: &str,
+    opts: &EncryptOptions,
+    dek: &[u8; 32],
+    version: u8,
+) -> Result<PasswordSlot> {
+    let use_password = !password.is_empty() || opts.keyfile.is_none();
+    let factors = Factors {
+        password: use_password,
+        keyfile: opts.keyfile.is_some(),
+        nfc: use_password && version >= 10,
+    };
+    let keyfile_hash = opts.keyfile.as_deref().map(hash_keyfile).transpose()?;
+    let password = password_input(password, factors.nfc);
+    let secret = secret_input(factors.password.then_some(&password), keyfile_hash.as_ref());
+    let mut salt = [0u8; 16];
+    fill(&mut salt).map_err(|e| anyhow!("OS RNG failed for salt: {e}"))?;
+    let kek = Zeroizing::new(derive_key(&secret, &salt, &opts.kdf)?);
+
+    PasswordSlot::seal(factors, opts.kdf, salt, &kek, dek)
+}
+
+/// Tries each password slot in turn and returns the data key with the index of the slot
+/// that opened.
+///
+/// A slot only counts as opened when its key matches the header's key commitment, so a
+/// crafted slot cannot steer decryption to a second key.
+fn unlock(
+    header: &Header,
+    password: &str,
+    opts: &DecryptOptions,
+) -> Result<(Zeroizing<[u8; 32]>, usize)> {
+    if !opts.shares.is_empty() {
+        return unlock_shares(header, &opts.shares);
+    }
+    if let Some(words) = &opts.recovery_words {
+        return unlock_recovery(header, words);
+    }
+    if !opts.identities.is_empty() {
+        // A password or keyfile given alongside still opens files with no slot for the keys
+        let fallback = !password.is_empty() || opts.keyfile.is_some();
+        match unlock_identities(header, &opts.identities) {
+            Err(e) if fallback && matches!(e.downcast_ref(), Some(Error::NotARecipient)) => {}
+            result => return result,
+        }
+    }
+
+    let mut keyfile_missing = false;
+    let mut commitment_mismatch = false;
+    let mut slot_corrupted = false;
+    for (index, slot) in header.slots.iter().enumerate() {
+        let KeySlot::Password(slot) = slot else {
+            continue;
+        };
+
+        // The header is untrusted: refuse expensive KDF costs before running Argon2
+        opts.kdf_limits.check(&slot.kdf)?;
+
+        let keyfile_hash = match (slot.factors.keyfile, opts.keyfile.as_deref()) {
+            (false, _) => None,
+            (true, Some(path)) => Some(hash_keyfile(path)?),
+            (true, None) => {
+                keyfile_missing = true;
+                continue;
+            }
+        };
+        let password = password_input(password, slot.factors.nfc);
+        let secret = secret_input(
+            slot.factors.password.then_some(&password),
+            keyfile_hash.as_ref(),
+        );
+        let kek = Zeroizing::new(derive_key(&secret, &slot.salt, &slot.kdf)?);
+        if !slot.check_key(&kek) {
+            continue;
+        }
+        match slot.open(&kek) {
+            Some(dek) if commits_to(header, &dek) => return Ok((dek, index)),
+            Some(_) => commitment_mismatch = true,
+            // The key check passed, so the wrapped key itself is damaged
+            None if slot.check.is_some() => slot_corrupted = true,
+            None => {}
+        }
+    }
+
+    if commitment_mismatch {
+        return Err(Error::KeyCommitmentMismatch.into());
+    }
+    if slot_corrupted {
+        anyhow::bail!("Key slot is corrupted (the password is correct)");
+    }
+    if keyfile_missing {
+        return Err(Error::KeyfileRequired.into());
+    }
+    Err(Error::WrongPassword.into())
+}
+
+fn unlock_identities(
+    header: &Header,
+    identities: &[Identity],
+) -> Result<(Zeroizing<[u8; 32]>, usize)> {
+    for (index, slot) in header.slots.iter().enumerate() {
+        for identity in identities {
+            let opened = match (slot, identity) {
+                (KeySlot::Hybrid(slot), Identity::Hybrid(key)) => slot.open(key),
+                (KeySlot::Ssh(slot), Identity::Ssh(key)) => slot.open(key),
+                _ => None,
+            };
+            match opened {
+                Some(dek) if commits_to(header, &dek) => return Ok((dek, index)),
+                Some(_) => return Err(Error::KeyCommitmentMismatch.into()),
+                None => {}
+            }
+        }
+    }
+    Err(Error::NotARecipient.into())
+}
+
+fn unlock_recovery(header: &Header, words: &str) -> Result<(Zeroizing<[u8; 32]>, usize)> {
+    let secret = recovery::parse(words)?;
+    let kek = recovery::derive_kek(&secret);
+    let mut found = false;
+    for (index, slot) in header.slots.iter().enumerate() {
+        let KeySlot::Recovery(slot) = slot else {
+            continue;
+        };
+        found = true;
+        match slot.open(&kek) {
+            Some(dek) if commits_to(header, &dek) => return Ok((dek, index)),
+            Some(_) => return Err(Error::KeyCommitmentMismatch.into()),
+            None => {}
+        }
+    }
+    if !found {
+        anyhow::bail!("This file has no recovery key");
+    }
+    Err(Error::WrongRecoveryKey.into())
+}
+
+/// Combines shares to open the file's share slot.
+///
+/// Every share is checked against the hash the slot keeps for its index, so a mistyped,
+/// foreign or duplicate share is reported by index rather than as a failed unlock.
+fn unlock_shares(header: &Header, texts: &[String]) -> Result<(Zeroizing<[u8; 32]>, usize)> {
+    let Some((index, slot)) = header.slots.iter().enumerate().find_map(|(i, s)| match s {
+        KeySlot::Shares(slot) => Some((i, slot)),
+        _ => None,
+    }) else {
+        anyhow::bail!("This file cannot be unlocked with shares");
+    };
+
+    let mut shares: Vec<Share> = Vec::with_capacity(texts.len());
+    for (position, text) in texts.iter().enumerate() {
+        let share =
+            Share::parse(text).with_context(|| format!("Share {} is malformed", position + 1))?;
+        let expected = slot.hashes.get(share.index as usize - 1);
+        if share.threshold != slot.threshold
+            || share.count != slot.count()
+            || expected.is_none_or(|h| !bool::from(share.hash().ct_eq(h)))
+        {
+            return Err(Error::InvalidShare { index: share.index }.into());
+        }
+        if shares.iter().any(|s| s.index == share.index) {
+            return Err(Error::DuplicateShare { index: share.index }.into());
+        }
+        shares.push(share);
+    }
+    if shares.len() < slot.threshold as usize {
+        return Err(Error::NotEnoughShares {
+            have: shares.len(),
+            need: slot.threshold,
+        }
+        .into());
+    }
 
-    argon
-        .hash_password_into(password.as_bytes(), salt, &mut key)
-        .map_err(|_| anyhow!("Key derivation failed"))?;
+    let chosen: Vec<&Share> = shares.iter().take(slot.threshold as usize).collect();
+    let kek = shamir::combine(&chosen);
+    match slot.open(&kek) {
+        Some(dek) if commits_to(header, &dek) => Ok((dek, index)),
+        Some(_) => Err(Error::KeyCommitmentMismatch.into()),
+        None => anyhow::bail!("Share slot is corrupted (the shares are correct)"),
+    }
+}
 
-    Ok(key)
+fn commits_to(header: &Header, dek: &[u8; 32]) -> bool {
+    match &header.commitment {
+        Some(commitment) => split_data_key(dek).1.ct_eq(commitment).into(),
+        None => true,
+    }
+}
+
+/// The key the payload is encrypted under. Before version 5 that is the data key itself.
+fn payload_key(header: &Header, dek: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+    match header.commitment {
+        Some(_) => split_data_key(dek).0,
+        None => Zeroizing::new(*dek),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn truncated_ciphertext_returns_error() {
+        let dir = std::env::temp_dir();
+        let uniq = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let in_path = dir.join(format!("truncated_{uniq}_in.bin"));
+        let out_path = dir.join(format!("truncated_{uniq}_out.bin"));
+
+        let factors = Factors {
+            password: true,
+            keyfile: false,
+            nfc: false,
+        };
+        let slot =
+            PasswordSlot::seal(factors, KdfParams::default(), [0; 16], &[0; 32], &[0; 32]).unwrap();
+        let header = Header::new(
+            Suite::Aes256Gcm,
+            0,
+            [0; 12],
+            [0; 32],
+            vec![KeySlot::Password(slot)],
+        );
+        let mut data = header.to_bytes().unwrap();
+        data.extend_from_slice(&[0u8; 15]); // truncated ciphertext
+        fs::write(&in_path, &data).unwrap();
+
+        let res = run_decrypt(Some(in_path.clone()), Some(out_path.clone()), "password");
+        assert!(
+            res.unwrap_err()
+                .to_string()
+                .contains("Ciphertext too short")
+        );
+
+        let _ = fs::remove_file(in_path);
+        let _ = fs::remove_file(out_path);
+    }
+
+    #[test]
+    fn password_change_keeps_version_9_passwords_as_typed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("v9.bin");
+        let out_path = dir.path().join("out.txt");
+
+        let opts = EncryptOptions::default();
+        let dek = [9u8; 32];
+        let (payload_key, commitment) = split_data_key(&dek);
+        let slot = seal_password_slot("old", &opts, &dek, 9).unwrap();
+        let mut header = Header::new(
+            Suite::Aes256Gcm,
+            0,
+            [1; 12],
+            commitment,
+            vec![KeySlot::Password(slot)],
+        );
+        header.version = 9;
+        let mut data = header.to_bytes().unwrap();
+        let aad = header.aad(&data).to_vec();
+        data.extend(
+            Suite::Aes256Gcm
+                .encrypt(&payload_key, &[1; 12], b"from version 9", &aad)
+                .unwrap(),
+        );
+        fs::write(&path, &data).unwrap();
+
+        let decomposed = "cafe\u{301}";
+        let unlock_opts = DecryptOptions::default();
+        run_change_password(Some(path.clone()), "old", decomposed, &unlock_opts, &opts).unwrap();
+        let (header, _) = Header::parse(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(header.version, 9);
+        let KeySlot::Password(slot) = &header.slots[0] else {
+            panic!("expected a password slot");
+        };
+        assert!(!slot.factors.nfc);
+
+        run_decrypt(Some(path.clone()), Some(out_path.clone()), decomposed).unwrap();
+        assert_eq!(fs::read(&out_path).unwrap(), b"from version 9");
+        // Without NFC the composed spelling is a different password
+        fs::remove_file(&out_path).unwrap();
+        assert!(run_decrypt(Some(path), Some(out_path), "caf\u{e9}").is_err());
+    }
 }
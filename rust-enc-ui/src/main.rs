@@ -1,15 +1,43 @@
 use anyhow::anyhow;
 use eframe::{NativeOptions, egui};
-use rust_enc_ui::{run_decrypt, run_encrypt};
+use rust_enc_ui::{
+    DecryptOptions, EncryptOptions, FileInfo, Padding, ShareSplit, Suite, format_utc,
+    keyring::Keyring,
+    paper::PaperBackup,
+    passphrase::{self, PassphraseOptions},
+    password_source::PasswordSource,
+    recipient, recovery, run_check, run_decrypt_with, run_encrypt_with, run_inspect, run_repair,
+    signing, strength,
+};
 use std::path::PathBuf;
-use zeroize::Zeroize;
+use std::time::{Duration, SystemTime};
+use zeroize::{Zeroize, Zeroizing};
+
+mod cli;
 
 fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if matches!(args[0].as_str(), "-h" | "--help" | "help") {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        if let Err(e) = cli::run(&args) {
+            eprintln!("Error: {e:#}\n\n{}", cli::USAGE);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let options = NativeOptions::default();
     eframe::run_native(
         "Rust Encryptor",
         options,
-        Box::new(|_cc| Ok(Box::new(App::default()))),
+        Box::new(|_cc| {
+            Ok(Box::new(App {
+                min_score: 3,
+                ..App::default()
+            }))
+        }),
     )
 }
 
@@ -18,8 +46,64 @@ struct App {
     mode_encrypt: bool,
     input_path: Option<PathBuf>,
     output_path: Option<PathBuf>,
+    keyfile_path: Option<PathBuf>,
+    header_path: Option<PathBuf>,
+    suite: Suite,
+    hide_size: bool,
+    checksum: bool,
+    parity: bool,
+    split: bool,
+    volume_mib: u64,
+    signing_key_path: Option<PathBuf>,
+    /// Public key files of recipients: RENC hybrid or OpenSSH `.pub` files.
+    recipient_paths: Vec<PathBuf>,
+    /// Secret key file to decrypt with instead of the password.
+    identity_path: Option<PathBuf>,
+    /// Set when the secret key is an OpenSSH key protected by a passphrase.
+    identity_locked: bool,
+    identity_passphrase: String,
+    /// The open keyring, if unlocked.
+    keyring: Option<Keyring>,
+    keyring_passphrase: String,
+    /// Nickname for the next contact or identity added to the keyring.
+    keyring_nickname: String,
+    /// Keyring contacts picked with "Encrypt to…".
+    chosen_contacts: Vec<String>,
+    /// Also try the keyring's identities when decrypting.
+    use_keyring_identities: bool,
+    comment: String,
+    expire: bool,
+    expire_days: u64,
+    allow_expired: bool,
+    use_shares: bool,
+    share_threshold: u8,
+    share_count: u8,
+    /// Shares typed or pasted for decryption, one per line.
+    shares: String,
+    recovery_key: bool,
+    /// Recovery words typed for decryption.
+    recovery_words: String,
+    /// Recovery words or a share to put on paper; empty backs up the input file.
+    paper_text: String,
+    /// Typed backup lines or QR text to restore from.
+    paper_import: String,
+    /// Header summary of the selected input, if it is an encrypted file.
+    file_info: Option<String>,
     password: String,
     confirm_password: String,
+    /// Passwords scoring below this (0 to 4) are refused when encrypting.
+    min_score: u8,
+    /// Estimate for the current password, cleared whenever it changes.
+    strength: Option<strength::Strength>,
+    passphrase_opts: PassphraseOptions,
+    use_password_source: bool,
+    /// Where to read the password instead of typing it, such as `cmd:pass show backup`.
+    password_source: Zeroizing<String>,
+    /// The last generated passphrase, shown until the next operation.
+    generated: String,
+    /// Shares and recovery words from the last encryption, shown until dismissed or the
+    /// next operation.
+    handout: Zeroizing<String>,
     status: String,
 }
 
@@ -35,34 +119,333 @@ impl eframe::App for App {
             ui.separator();
 
             ui.horizontal(|ui| {
-                if ui.button("Choose input file…").clicked() {
-                    if let Some(p) = rfd::FileDialog::new().pick_file() {
-                        self.input_path = Some(p);
-                    }
+                if ui.button("Choose input file…").clicked()
+                    && let Some(p) = rfd::FileDialog::new().pick_file()
+                {
+                    self.file_info = run_inspect(Some(p.clone())).ok().map(|i| describe(&i));
+                    self.input_path = Some(p);
                 }
                 if let Some(p) = &self.input_path {
                     ui.label(p.display().to_string());
                 }
             });
+            if !self.mode_encrypt {
+                if let Some(info) = &self.file_info {
+                    ui.label(info);
+                }
+                ui.checkbox(&mut self.allow_expired, "Decrypt even if expired");
+                ui.horizontal(|ui| {
+                    if ui.button("Decrypt with secret key…").clicked()
+                        && let Some(p) = rfd::FileDialog::new().pick_file()
+                    {
+                        self.identity_locked = recipient::needs_passphrase(&p);
+                        self.identity_path = Some(p);
+                    }
+                    if let Some(p) = &self.identity_path {
+                        ui.label(p.display().to_string());
+                        if ui.small_button("✖").clicked() {
+                            self.identity_path = None;
+                            self.identity_locked = false;
+                        }
+                    }
+                });
+                if self.keyring.is_some() {
+                    ui.checkbox(
+                        &mut self.use_keyring_identities,
+                        "Decrypt with my keyring identities",
+                    );
+                }
+                if self.identity_path.is_some() && self.identity_locked {
+                    ui.horizontal(|ui| {
+                        ui.label("Key passphrase:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.identity_passphrase)
+                                .password(true),
+                        );
+                    });
+                }
+                ui.label("Shares instead of password (one per line):");
+                ui.add(egui::TextEdit::multiline(&mut self.shares).desired_rows(3));
+                ui.label("Recovery words instead of password:");
+                ui.add(egui::TextEdit::multiline(&mut self.recovery_words).desired_rows(2));
+                if !self.recovery_words.trim().is_empty() {
+                    match recovery::check_words(&self.recovery_words) {
+                        Ok(()) => ui.label("Recovery words look right ✅"),
+                        Err(e) => ui.colored_label(egui::Color32::RED, e.to_string()),
+                    };
+                }
+            }
 
             ui.horizontal(|ui| {
-                if ui.button("Choose output file…").clicked() {
-                    if let Some(p) = rfd::FileDialog::new().save_file() {
-                        self.output_path = Some(p);
-                    }
+                if ui.button("Choose output file…").clicked()
+                    && let Some(p) = rfd::FileDialog::new().save_file()
+                {
+                    self.output_path = Some(p);
                 }
                 if let Some(p) = &self.output_path {
                     ui.label(p.display().to_string());
                 }
             });
 
+            ui.horizontal(|ui| {
+                let label = if self.mode_encrypt {
+                    "Detach header to…"
+                } else {
+                    "Detached header…"
+                };
+                if ui.button(label).clicked() {
+                    let dialog = rfd::FileDialog::new().add_filter("Detached header", &["rhdr"]);
+                    let picked = if self.mode_encrypt {
+                        dialog.save_file()
+                    } else {
+                        dialog.pick_file()
+                    };
+                    if picked.is_some() {
+                        self.header_path = picked;
+                    }
+                }
+                if let Some(p) = &self.header_path {
+                    ui.label(p.display().to_string());
+                    if ui.small_button("✖").clicked() {
+                        self.header_path = None;
+                    }
+                }
+            });
+
             ui.separator();
             ui.label("Password (never stored):");
-            ui.add(egui::TextEdit::singleline(&mut self.password).password(true));
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.use_password_source, "Read password from");
+                if self.use_password_source {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut *self.password_source)
+                            .hint_text("env:NAME, fd:N, file:PATH or cmd:COMMAND"),
+                    );
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add(egui::TextEdit::singleline(&mut self.password).password(true))
+                    .changed()
+                {
+                    self.strength = None;
+                }
+                if self.mode_encrypt {
+                    if ui.button("Generate").clicked() {
+                        match passphrase::generate(&self.passphrase_opts) {
+                            Ok(pass) => {
+                                self.password.zeroize();
+                                self.confirm_password.zeroize();
+                                self.generated.zeroize();
+                                self.password.push_str(&pass.text);
+                                self.confirm_password.push_str(&pass.text);
+                                self.strength = None;
+                                self.generated.push_str(&pass.text);
+                                self.status =
+                                    format!("Generated a {:.0}-bit passphrase", pass.entropy_bits);
+                            }
+                            Err(e) => self.status = format!("Error: {e:#}"),
+                        }
+                    }
+                    ui.menu_button("Words…", |ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut self.passphrase_opts.words)
+                                .range(1..=passphrase::MAX_WORDS)
+                                .suffix(" words"),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Separator:");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.passphrase_opts.separator)
+                                    .desired_width(30.0),
+                            );
+                        });
+                        ui.checkbox(&mut self.passphrase_opts.digit, "Add a digit");
+                        ui.label(format!(
+                            "{:.0} bits",
+                            passphrase::entropy_bits(&self.passphrase_opts)
+                        ));
+                    });
+                }
+                if ui.button("Choose keyfile…").clicked()
+                    && let Some(p) = rfd::FileDialog::new().pick_file()
+                {
+                    self.keyfile_path = Some(p);
+                }
+                if let Some(p) = &self.keyfile_path {
+                    ui.label(p.display().to_string());
+                    if ui.small_button("✖").clicked() {
+                        self.keyfile_path = None;
+                    }
+                }
+            });
 
             if self.mode_encrypt {
+                if !self.generated.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label("Write it down:");
+                        ui.add(egui::Label::new(egui::RichText::new(&self.generated).monospace()));
+                    });
+                }
+                if !self.password.is_empty() {
+                    let strength = self
+                        .strength
+                        .get_or_insert_with(|| strength::estimate(&self.password));
+                    let color = match strength.score {
+                        0 | 1 => egui::Color32::RED,
+                        2 => egui::Color32::ORANGE,
+                        _ => egui::Color32::DARK_GREEN,
+                    };
+                    ui.colored_label(
+                        color,
+                        format!(
+                            "Strength: {} ({} of 4), cracked offline in {}",
+                            strength.label(),
+                            strength.score,
+                            strength.crack_time()
+                        ),
+                    );
+                    let advice = strength.advice();
+                    if !advice.is_empty() {
+                        ui.label(advice);
+                    }
+                }
+                egui::ComboBox::from_label("Minimum strength")
+                    .selected_text(self.min_score.to_string())
+                    .show_ui(ui, |ui| {
+                        for score in 0..=4 {
+                            ui.selectable_value(&mut self.min_score, score, score.to_string());
+                        }
+                    });
+
                 ui.label("Confirm password:");
                 ui.add(egui::TextEdit::singleline(&mut self.confirm_password).password(true));
+
+                egui::ComboBox::from_label("Cipher")
+                    .selected_text(self.suite.name())
+                    .show_ui(ui, |ui| {
+                        for suite in Suite::ALL {
+                            ui.selectable_value(&mut self.suite, suite, suite.name());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Comment (readable without password):");
+                    ui.text_edit_singleline(&mut self.comment);
+                });
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.expire, "Expires after").changed()
+                        && self.expire_days == 0
+                    {
+                        self.expire_days = 365;
+                    }
+                    ui.add_enabled(
+                        self.expire,
+                        egui::DragValue::new(&mut self.expire_days)
+                            .range(1..=36_500)
+                            .suffix(" days"),
+                    );
+                });
+                ui.checkbox(&mut self.hide_size, "Hide exact file size (padding)");
+                ui.checkbox(
+                    &mut self.checksum,
+                    "Add checksum (integrity check without password)",
+                );
+                ui.checkbox(
+                    &mut self.parity,
+                    "Add error correction (Reed-Solomon, +12.5% size)",
+                );
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.split, "Split into volumes of").changed()
+                        && self.volume_mib == 0
+                    {
+                        // Largest whole MiB that fits on FAT32
+                        self.volume_mib = 4095;
+                    }
+                    ui.add_enabled(
+                        self.split,
+                        egui::DragValue::new(&mut self.volume_mib)
+                            .range(1..=u64::MAX >> 20)
+                            .suffix(" MiB"),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.use_shares, "Split key into").changed()
+                        && self.share_count == 0
+                    {
+                        (self.share_threshold, self.share_count) = (3, 5);
+                    }
+                    ui.add_enabled(
+                        self.use_shares,
+                        egui::DragValue::new(&mut self.share_count)
+                            .range(1..=255)
+                            .suffix(" shares"),
+                    );
+                    ui.label("any");
+                    ui.add_enabled(
+                        self.use_shares,
+                        egui::DragValue::new(&mut self.share_threshold)
+                            .range(1..=self.share_count.max(1)),
+                    );
+                    ui.label("unlock (password optional)");
+                });
+
+                ui.checkbox(
+                    &mut self.recovery_key,
+                    "Create a recovery key (24 words, shown once)",
+                );
+
+                ui.horizontal(|ui| {
+                    if ui.button("Encrypt to public key…").clicked()
+                        && let Some(paths) = rfd::FileDialog::new().pick_files()
+                    {
+                        self.recipient_paths.extend(paths);
+                    }
+                    if !self.recipient_paths.is_empty() {
+                        ui.label(format!("{} recipients", self.recipient_paths.len()));
+                        if ui.small_button("✖").clicked() {
+                            self.recipient_paths.clear();
+                        }
+                    }
+                });
+
+                if let Some(keyring) = &self.keyring {
+                    ui.horizontal(|ui| {
+                        ui.menu_button("Encrypt to…", |ui| {
+                            for contact in keyring.contacts() {
+                                let mut chosen = self.chosen_contacts.contains(&contact.nickname);
+                                if ui
+                                    .checkbox(&mut chosen, &contact.nickname)
+                                    .on_hover_text(contact.fingerprint())
+                                    .changed()
+                                {
+                                    if chosen {
+                                        self.chosen_contacts.push(contact.nickname.clone());
+                                    } else {
+                                        self.chosen_contacts.retain(|n| *n != contact.nickname);
+                                    }
+                                }
+                            }
+                        });
+                        if !self.chosen_contacts.is_empty() {
+                            ui.label(self.chosen_contacts.join(", "));
+                        }
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Sign with key…").clicked()
+                        && let Some(p) = rfd::FileDialog::new().pick_file()
+                    {
+                        self.signing_key_path = Some(p);
+                    }
+                    if let Some(p) = &self.signing_key_path {
+                        ui.label(p.display().to_string());
+                        if ui.small_button("✖").clicked() {
+                            self.signing_key_path = None;
+                        }
+                    }
+                });
             }
 
             ui.separator();
@@ -76,160 +459,453 @@ impl eframe::App for App {
                 .clicked()
             {
                 self.status.clear();
-                let res = if self.mode_encrypt {
+                self.handout.zeroize();
+                let res = if let Err(e) = self.read_password_source() {
+                    Err(e)
+                } else if self.mode_encrypt {
+                    // The password is optional only when something else can unlock the file
+                    let password_used = !self.password.is_empty()
+                        || (self.keyfile_path.is_none()
+                            && !self.use_shares
+                            && self.recipient_paths.is_empty()
+                            && self.chosen_contacts.is_empty());
+                    let strength = strength::estimate(&self.password);
                     if self.password != self.confirm_password {
                         Err(anyhow!("Passwords do not match"))
+                    } else if password_used && strength.score < self.min_score {
+                        Err(anyhow!(
+                            "Password is {} ({} of 4, at least {} required). {}",
+                            strength.label(),
+                            strength.score,
+                            self.min_score,
+                            strength.advice()
+                        ))
                     } else {
-                        run_encrypt(
-                            self.input_path.clone(),
-                            self.output_path.clone(),
-                            &self.password,
-                        )
+                        self.signing_key_path
+                            .as_deref()
+                            .map(signing::read_signing_key)
+                            .transpose()
+                            .and_then(|signing_key| {
+                                let mut recipients = self
+                                    .recipient_paths
+                                    .iter()
+                                    .map(|p| recipient::read_recipient(p))
+                                    .collect::<anyhow::Result<Vec<_>>>()?;
+                                if let Some(keyring) = &self.keyring {
+                                    for nickname in &self.chosen_contacts {
+                                        recipients.push(keyring.resolve(nickname)?);
+                                    }
+                                }
+                                let opts = EncryptOptions {
+                                    keyfile: self.keyfile_path.clone(),
+                                    suite: self.suite,
+                                    padding: if self.hide_size {
+                                        Padding::Padme
+                                    } else {
+                                        Padding::None
+                                    },
+                                    signing_key,
+                                    checksum: self.checksum,
+                                    parity: if self.parity { 4 } else { 0 },
+                                    volume_size: self.split.then_some(self.volume_mib << 20),
+                                    detached_header: self.header_path.clone(),
+                                    comment: (!self.comment.is_empty())
+                                        .then(|| self.comment.clone()),
+                                    expires: self.expire.then(|| {
+                                        SystemTime::now()
+                                            + Duration::from_secs(self.expire_days * 86_400)
+                                    }),
+                                    shares: self.use_shares.then_some(ShareSplit {
+                                        threshold: self.share_threshold,
+                                        count: self.share_count,
+                                    }),
+                                    recovery_key: self.recovery_key,
+                                    recipients,
+                                    ..EncryptOptions::default()
+                                };
+                                run_encrypt_with(
+                                    self.input_path.clone(),
+                                    self.output_path.clone(),
+                                    &self.password,
+                                    &opts,
+                                )
+                            })
+                            .map(|report| {
+                                if !report.shares.is_empty() {
+                                    self.handout.push_str("Give one share to each holder:");
+                                    for share in &report.shares {
+                                        self.handout.push('\n');
+                                        self.handout.push_str(share);
+                                    }
+                                }
+                                if let Some(words) = &report.recovery_words {
+                                    if !self.handout.is_empty() {
+                                        self.handout.push('\n');
+                                    }
+                                    self.handout.push_str("Recovery key, write it down now:\n");
+                                    self.handout.push_str(words);
+                                }
+                                "Success ✅".to_string()
+                            })
                     }
                 } else {
-                    run_decrypt(
+                    let identity = self
+                        .identity_path
+                        .as_deref()
+                        .map(|p| {
+                            recipient::read_identity(
+                                p,
+                                self.identity_locked
+                                    .then_some(self.identity_passphrase.as_str()),
+                            )
+                        })
+                        .transpose();
+                    identity.and_then(|identity| {
+                    let mut opts = DecryptOptions {
+                        keyfile: self.keyfile_path.clone(),
+                        detached_header: self.header_path.clone(),
+                        allow_expired: self.allow_expired,
+                        shares: self
+                            .shares
+                            .lines()
+                            .filter(|l| !l.trim().is_empty())
+                            .map(str::to_string)
+                            .collect(),
+                        recovery_words: (!self.recovery_words.trim().is_empty())
+                            .then(|| self.recovery_words.clone()),
+                        identities: identity
+                            .into_iter()
+                            .chain(
+                                self.keyring
+                                    .as_ref()
+                                    .filter(|_| self.use_keyring_identities)
+                                    .into_iter()
+                                    .flat_map(|k| k.identities().map(|(_, i)| i.clone())),
+                            )
+                            .collect(),
+                        ..DecryptOptions::default()
+                    };
+                    let report = run_decrypt_with(
                         self.input_path.clone(),
                         self.output_path.clone(),
                         &self.password,
-                    )
+                        &opts,
+                    );
+                    // The copies handed to decryption are wiped like the fields they came from
+                    opts.shares.iter_mut().for_each(Zeroize::zeroize);
+                    opts.recovery_words.zeroize();
+                    report
+                    })
+                    .map(|report| {
+                        let mut message = match report.signer {
+                            Some(signer) => {
+                                format!("Success ✅ — signed by {}", signer.fingerprint)
+                            }
+                            None => "Success ✅ (not signed)".to_string(),
+                        };
+                        if let Some(repair) = report.repair
+                            && repair.damaged_bytes() > 0
+                        {
+                            message += &format!(
+                                " — repaired {} damaged bytes in memory",
+                                repair.repaired_bytes()
+                            );
+                        }
+                        if report.integrity_skipped {
+                            message += " — integrity data was damaged and skipped";
+                        }
+                        message
+                    })
                 };
 
                 match res {
-                    Ok(_) => self.status = "Success ✅".to_string(),
+                    Ok(message) => self.status = message,
                     Err(e) => self.status = format!("Error: {e:#}"),
                 }
 
                 // best-effort wipe
                 self.password.zeroize();
                 self.confirm_password.zeroize();
+                self.strength = None;
+                self.shares.zeroize();
+                self.recovery_words.zeroize();
+                self.identity_passphrase.zeroize();
+                self.generated.zeroize();
+            }
+
+            if !self.mode_encrypt && ui.button("Check integrity (no password)").clicked() {
+                self.status = match run_check(self.input_path.clone()) {
+                    Ok(report) if report.is_intact() => {
+                        format!("Intact ✅ ({} bytes checked)", report.checked_len)
+                    }
+                    Ok(report) => {
+                        let ranges: Vec<String> = report
+                            .damaged
+                            .iter()
+                            .map(|r| format!("{}..{}", r.start, r.end))
+                            .collect();
+                        format!("Damaged ❌ at bytes {}", ranges.join(", "))
+                    }
+                    Err(e) => format!("Error: {e:#}"),
+                };
+            }
+
+            if !self.mode_encrypt && ui.button("Repair (no password)").clicked() {
+                self.status = match run_repair(self.input_path.clone()) {
+                    Ok(report) if report.damaged.is_empty() => "No damage found ✅".to_string(),
+                    Ok(report) if report.is_complete() => format!(
+                        "Repaired ✅ {} damaged bytes in {} chunks",
+                        report.repaired_bytes(),
+                        report.damaged.len()
+                    ),
+                    Ok(report) => format!(
+                        "Partly repaired ❌ {} of {} damaged bytes; too much damage to fix the rest",
+                        report.repaired_bytes(),
+                        report.damaged_bytes()
+                    ),
+                    Err(e) => format!("Error: {e:#}"),
+                };
             }
 
+            ui.separator();
+            ui.collapsing("Keyring", |ui| self.keyring_ui(ui));
+
+            ui.collapsing("Paper backup", |ui| {
+                ui.label("Recovery words or a share (empty: back up the input file):");
+                ui.add(egui::TextEdit::multiline(&mut self.paper_text).desired_rows(2));
+                if ui.button("Save printable page…").clicked()
+                    && let Some(p) = rfd::FileDialog::new()
+                        .add_filter("Web page", &["html"])
+                        .save_file()
+                {
+                    let text = self.paper_text.trim();
+                    let backup = if text.starts_with("renc-share:") {
+                        Ok(PaperBackup::Share(text.to_string().into()))
+                    } else if !text.is_empty() {
+                        Ok(PaperBackup::RecoveryWords(text.to_string().into()))
+                    } else {
+                        self.input_path
+                            .as_deref()
+                            .ok_or_else(|| anyhow!("No input file selected"))
+                            .and_then(|input| Ok(std::fs::read(input)?))
+                            .map(PaperBackup::File)
+                    };
+                    self.status = match backup.and_then(|b| b.write_page(&p).map(|()| b)) {
+                        Ok(b) => format!("Saved paper backup of the {} ✅", b.kind_name()),
+                        Err(e) => format!("Error: {e:#}"),
+                    };
+                    self.paper_text.zeroize();
+                }
+
+                ui.label("Restore from the typed lines, the QR code's text or a scan of the page:");
+                ui.add(egui::TextEdit::multiline(&mut self.paper_import).desired_rows(4));
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        self.status = self.restore(PaperBackup::import(&self.paper_import));
+                        self.paper_import.zeroize();
+                    }
+                    if ui.button("Restore from QR image…").clicked()
+                        && let Some(p) = rfd::FileDialog::new()
+                            .add_filter("Image", &["png", "jpg", "jpeg"])
+                            .pick_file()
+                    {
+                        self.status = self.restore(PaperBackup::import_image(&p));
+                    }
+                });
+            });
+
             if !self.status.is_empty() {
                 ui.separator();
                 ui.label(&self.status);
             }
+            if !self.handout.is_empty() {
+                ui.separator();
+                ui.label(egui::RichText::new(self.handout.as_str()).monospace());
+                if ui.button("Done, hide them").clicked() {
+                    self.handout.zeroize();
+                }
+            }
         });
     }
 }
 
-fn run_encrypt(input: Option<PathBuf>, output: Option<PathBuf>, password: &str) -> Result<()> {
-    let in_path = input.context("No input file selected")?;
-    let out_path = output.context("No output file selected")?;
-    let mut plaintext = fs::read(&in_path)
-        .with_context(|| format!("Reading {}", in_path.display()))?;
-
-    // Derive key
-    let mut salt = [0u8; 16];
-    fill(&mut salt).map_err(|e| anyhow!("OS RNG failed for salt: {e}"))?;
-    let mut key = derive_key(password, &salt)?;
-
-    // AEAD
-    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
-    let mut nonce = [0u8; 12];
-    fill(&mut nonce).map_err(|e| anyhow!("OS RNG failed for nonce: {e}"))?;
-
-    let ciphertext = cipher
-        .encrypt((&nonce).into(), plaintext.as_ref())
-        .map_err(|_| anyhow!("Encryption failed"))?;
-
-    // Write: MAGIC|VERSION|SALT|NONCE|CIPHERTEXT
-    let mut out = Vec::with_capacity(4 + 1 + 16 + 12 + ciphertext.len());
-    out.extend_from_slice(MAGIC);
-    out.push(VERSION);
-    out.extend_from_slice(&salt);
-    out.extend_from_slice(&nonce);
-    out.extend_from_slice(&ciphertext);
-
-    let mut f = fs::File::create(&out_path)
-        .with_context(|| format!("Creating {}", out_path.display()))?;
-    f.write_all(&out)?;
-    f.flush()?;
-
-    // Wipe sensitive material from memory before returning
-    plaintext.zeroize();
-    key.zeroize();
-
-    Ok(())
-}
-
-fn run_decrypt(input: Option<PathBuf>, output: Option<PathBuf>, password: &str) -> Result<()> {
-    let in_path = input.context("No input file selected")?;
-    let out_path = output.context("No output file selected")?;
-    let data = fs::read(&in_path).with_context(|| format!("Reading {}", in_path.display()))?;
-
-    // Parse header
-    if data.len() < 4 + 1 + 16 + 12 {
-        anyhow::bail!("File too short");
-    }
-    if &data[0..4] != MAGIC {
-        anyhow::bail!("Bad magic");
-    }
-    if data[4] != VERSION {
-        anyhow::bail!("Unsupported version");
+impl App {
+    /// Replaces the typed password with the one from the chosen source, if any.
+    fn read_password_source(&mut self) -> anyhow::Result<()> {
+        if !self.use_password_source {
+            return Ok(());
+        }
+        let password = PasswordSource::parse(&self.password_source)?.read()?;
+        self.password.zeroize();
+        self.confirm_password.zeroize();
+        self.password.push_str(&password);
+        self.confirm_password.push_str(&password);
+        self.strength = None;
+        Ok(())
     }
 
-    let salt = &data[5..21];
-    let nonce = &data[21..33];
-    let ciphertext = &data[33..];
-    if ciphertext.len() < 16 {
-        anyhow::bail!("Ciphertext too short (missing authentication tag)");
+    /// Puts a paper backup where it is used and says what happened.
+    fn restore(&mut self, backup: anyhow::Result<PaperBackup>) -> String {
+        match backup {
+            Ok(PaperBackup::RecoveryWords(words)) => {
+                self.recovery_words.zeroize();
+                self.recovery_words.push_str(&words);
+                "Restored the recovery key into the decrypt form ✅".to_string()
+            }
+            Ok(PaperBackup::Share(share)) => {
+                if !self.shares.is_empty() && !self.shares.ends_with('\n') {
+                    self.shares.push('\n');
+                }
+                self.shares += &share;
+                "Restored the share into the decrypt form ✅".to_string()
+            }
+            Ok(PaperBackup::File(data)) => match rfd::FileDialog::new().save_file() {
+                Some(p) => match std::fs::write(&p, data) {
+                    Ok(()) => format!("Restored file to {} ✅", p.display()),
+                    Err(e) => format!("Error: {e}"),
+                },
+                None => "Restore cancelled".to_string(),
+            },
+            Err(e) => format!("Error: {e:#}"),
+        }
     }
 
-    let mut key = derive_key(password, salt)?;
-    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
-
-    let mut plaintext = cipher
-        .decrypt(nonce.into(), ciphertext)
-        .map_err(|_| anyhow!("Decryption failed (wrong password or corrupted file)"))?;
-
-    fs::write(&out_path, &plaintext)
-        .with_context(|| format!("Writing {}", out_path.display()))?;
-
-    plaintext.zeroize();
-    key.zeroize();
-
-    Ok(())
-}
-
-fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
-    // Tunable Argon2id params (defaults OK to start; consider raising memory for production)
-    let argon = Argon2::default();
-    let mut key = [0u8; 32];
-
-    argon
-        .hash_password_into(password.as_bytes(), salt, &mut key)
-        .map_err(|_| anyhow!("Key derivation failed"))?;
+    fn keyring_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(keyring) = &mut self.keyring else {
+            let Some(path) = Keyring::default_path() else {
+                ui.label("No configuration directory for a keyring");
+                return;
+            };
+            ui.label(path.display().to_string());
+            ui.horizontal(|ui| {
+                ui.label("Master passphrase:");
+                ui.add(egui::TextEdit::singleline(&mut self.keyring_passphrase).password(true));
+            });
+            ui.horizontal(|ui| {
+                let opened = if ui.button("Open").clicked() {
+                    Some(Keyring::open(&path, &self.keyring_passphrase))
+                } else if ui.button("Create").clicked() {
+                    Some(Keyring::create(&path, &self.keyring_passphrase))
+                } else {
+                    None
+                };
+                if let Some(opened) = opened {
+                    match opened {
+                        Ok(keyring) => {
+                            self.keyring = Some(keyring);
+                            self.status = "Keyring open ✅".to_string();
+                        }
+                        Err(e) => self.status = format!("Error: {e:#}"),
+                    }
+                    self.keyring_passphrase.zeroize();
+                }
+            });
+            return;
+        };
+
+        let mut changed = false;
+        let mut lock = false;
+        ui.label("Contacts:");
+        let mut removed = None;
+        for contact in keyring.contacts() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}  {}", contact.nickname, contact.fingerprint()));
+                if ui.small_button("✖").clicked() {
+                    removed = Some(contact.nickname.clone());
+                }
+            });
+        }
+        if let Some(nickname) = removed {
+            keyring.remove_contact(&nickname);
+            self.chosen_contacts.retain(|n| *n != nickname);
+            changed = true;
+        }
+        ui.label("My identities:");
+        let mut removed = None;
+        for (nickname, identity) in keyring.identities() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{nickname}  {}",
+                    identity.recipient().fingerprint()
+                ));
+                if ui.small_button("✖").clicked() {
+                    removed = Some(nickname.to_string());
+                }
+            });
+        }
+        if let Some(nickname) = removed {
+            keyring.remove_identity(&nickname);
+            changed = true;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Nickname:");
+            ui.text_edit_singleline(&mut self.keyring_nickname);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Key passphrase, for protected SSH keys:");
+            ui.add(egui::TextEdit::singleline(&mut self.identity_passphrase).password(true));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Add contact…").clicked()
+                && let Some(p) = rfd::FileDialog::new().pick_file()
+            {
+                match recipient::read_recipient(&p)
+                    .and_then(|r| keyring.add_contact(&self.keyring_nickname, r))
+                {
+                    Ok(()) => changed = true,
+                    Err(e) => self.status = format!("Error: {e:#}"),
+                }
+            }
+            if ui.button("Add my identity…").clicked()
+                && let Some(p) = rfd::FileDialog::new().pick_file()
+            {
+                let passphrase = (!self.identity_passphrase.is_empty())
+                    .then_some(self.identity_passphrase.as_str());
+                match recipient::read_identity(&p, passphrase)
+                    .and_then(|i| keyring.add_identity(&self.keyring_nickname, i))
+                {
+                    Ok(()) => changed = true,
+                    Err(e) => self.status = format!("Error: {e:#}"),
+                }
+                self.identity_passphrase.zeroize();
+            }
+            lock = ui.button("Lock").clicked();
+        });
 
-    Ok(key)
+        if lock {
+            self.keyring = None;
+            self.chosen_contacts.clear();
+            self.use_keyring_identities = false;
+        } else if changed && let Some(keyring) = &self.keyring {
+            self.keyring_nickname.clear();
+            self.status = match keyring.save() {
+                Ok(()) => "Keyring saved ✅".to_string(),
+                Err(e) => format!("Error: {e:#}"),
+            };
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-
-    #[test]
-    fn truncated_ciphertext_returns_error() {
-        let dir = std::env::temp_dir();
-        let uniq = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let in_path = dir.join(format!("truncated_{uniq}_in.bin"));
-        let out_path = dir.join(format!("truncated_{uniq}_out.bin"));
-
-        let mut data = Vec::new();
-        data.extend_from_slice(MAGIC);
-        data.push(VERSION);
-        data.extend_from_slice(&[0u8; 16]); // salt
-        data.extend_from_slice(&[0u8; 12]); // nonce
-        data.extend_from_slice(&[0u8; 15]); // truncated ciphertext
-        fs::write(&in_path, &data).unwrap();
-
-        let res = run_decrypt(Some(in_path.clone()), Some(out_path.clone()), "password");
-        assert!(res.unwrap_err().to_string().contains("Ciphertext too short"));
-
-        let _ = fs::remove_file(in_path);
-        let _ = fs::remove_file(out_path);
+/// One-line summary of a file header for the status area.
+fn describe(info: &FileInfo) -> String {
+    let mut text = format!("Format v{}, {}", info.version, info.suite.name());
+    for (set, name) in [
+        (info.padded, "padded"),
+        (info.signed, "signed"),
+        (info.checksum, "checksum"),
+        (info.parity, "parity"),
+    ] {
+        if set {
+            text += ", ";
+            text += name;
+        }
+    }
+    if let Some(expires) = info.expires {
+        text += &format!("\nExpires: {}", format_utc(expires));
+    }
+    if let Some(comment) = &info.comment {
+        text += &format!("\nComment: {comment}");
     }
+    text
 }
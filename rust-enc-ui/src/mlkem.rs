@@ -0,0 +1,112 @@
+//! ML-KEM-768 (FIPS 203), the lattice-based half of hybrid recipients, from the RustCrypto
+//! `ml-kem` crate.
+//!
+//! The decapsulation key is kept as its 64-byte seed `d | z` and expanded when needed.
+
+use anyhow::{Result, anyhow};
+use ml_kem::{B32, Decapsulate, DecapsulationKey768, EncapsulationKey768, KeyExport, Seed};
+use zeroize::{Zeroize, Zeroizing};
+
+pub(crate) const SEED_LEN: usize = 64;
+pub(crate) const EK_LEN: usize = 1184;
+pub(crate) const CT_LEN: usize = 1088;
+
+fn expand(seed: &[u8; SEED_LEN]) -> DecapsulationKey768 {
+    let mut seed = Seed::from(*seed);
+    let key = DecapsulationKey768::from_seed(seed);
+    seed.as_mut_slice().zeroize();
+    key
+}
+
+/// Moves a secret out of the crate's array type, wiping the original.
+fn take_secret(mut secret: B32) -> Zeroizing<[u8; 32]> {
+    let copy = Zeroizing::new(secret.into());
+    secret.as_mut_slice().zeroize();
+    copy
+}
+
+/// The encapsulation key, after the crate's FIPS 203 input checks.
+fn encapsulation_key(ek: &[u8; EK_LEN]) -> Result<EncapsulationKey768> {
+    EncapsulationKey768::new(&(*ek).into()).map_err(|_| anyhow!("Invalid ML-KEM-768 public key"))
+}
+
+/// The encapsulation (public) key for a 64-byte seed `d | z`.
+pub(crate) fn public_key(seed: &[u8; SEED_LEN]) -> [u8; EK_LEN] {
+    expand(seed).encapsulation_key().to_bytes().into()
+}
+
+/// Whether `ek` passes the encapsulation key checks.
+pub(crate) fn is_valid_public_key(ek: &[u8; EK_LEN]) -> bool {
+    encapsulation_key(ek).is_ok()
+}
+
+/// Encapsulates to `ek` with the randomness `m`; returns the shared secret and ciphertext.
+pub(crate) fn encapsulate(
+    ek: &[u8; EK_LEN],
+    m: &[u8; 32],
+) -> Result<(Zeroizing<[u8; 32]>, [u8; CT_LEN])> {
+    let mut m = B32::from(*m);
+    let (c, key) = encapsulation_key(ek)?.encapsulate_deterministic(&m);
+    m.as_mut_slice().zeroize();
+    Ok((take_secret(key), c.into()))
+}
+
+/// Decapsulates `c`. A ciphertext that does not re-encrypt to itself yields a
+/// pseudorandom key derived from `z` (implicit rejection), never an error.
+pub(crate) fn decapsulate(seed: &[u8; SEED_LEN], c: &[u8; CT_LEN]) -> Zeroizing<[u8; 32]> {
+    take_secret(expand(seed).decapsulate(&(*c).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    fn seed() -> [u8; SEED_LEN] {
+        std::array::from_fn(|i| i as u8)
+    }
+
+    // Vectors from OpenSSL 3.5: `genpkey -algorithm ML-KEM-768 -pkeyopt hexseed:00..3f`,
+    // then `pkeyutl -encap -pkeyopt hexikme:64..83` and `-decap` of a corrupted ciphertext.
+    #[test]
+    fn matches_reference_vectors() {
+        let ek = public_key(&seed());
+        assert_eq!(
+            hex::encode(sha2::Sha256::digest(ek)),
+            "0b7934c83125c788995e2ba6bd761e33046b3e40571be53e023309a29f398cc9"
+        );
+        assert!(is_valid_public_key(&ek));
+
+        let m: [u8; 32] = std::array::from_fn(|i| 100 + i as u8);
+        let (key, c) = encapsulate(&ek, &m).unwrap();
+        assert_eq!(
+            hex::encode(sha2::Sha256::digest(c)),
+            "57fe559432dbb3c5547c73f155820622f7efdd532e4330360a36ebf7d2ddec55"
+        );
+        assert_eq!(
+            hex::encode(key.as_slice()),
+            "c5a74110c158acbaf9c01deb86fa6cc10c14533feda54bec1fdd000d61f07e4e"
+        );
+        assert_eq!(*decapsulate(&seed(), &c), *key);
+
+        let mut corrupted = c;
+        corrupted[0] = 0xff;
+        assert_eq!(
+            hex::encode(decapsulate(&seed(), &corrupted).as_slice()),
+            "ce216030a106fe55f9029f31e972e3b092a173937b7918b138e180f3fe7f2f1b"
+        );
+    }
+
+    #[test]
+    fn out_of_range_public_key_rejected() {
+        let mut ek = public_key(&seed());
+        // First coefficient = 0xfff > q
+        ek[0] = 0xff;
+        ek[1] |= 0x0f;
+        assert!(!is_valid_public_key(&ek));
+        // Last coefficient before the 32-byte seed of A
+        let mut ek = public_key(&seed());
+        ek[EK_LEN - 33] = 0xff;
+        assert!(!is_valid_public_key(&ek));
+    }
+}
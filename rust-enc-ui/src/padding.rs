@@ -0,0 +1,142 @@
+//! Length hiding. A padded payload is `LEN (u64 LE) | DATA | ZEROS`, encrypted as a whole,
+//! so the true length is only visible after decryption.
+
+use anyhow::{Result, bail};
+
+/// Largest bucket size; the whole padded payload is held in memory.
+pub const MAX_PADDING_BUCKET: u64 = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Padding {
+    /// Ciphertext length reveals the exact plaintext length.
+    #[default]
+    None,
+    /// PADMÉ: at most 12% overhead, leaking O(log log n) bits of the length.
+    Padme,
+    /// Round up to a multiple of this many bytes.
+    Bucket(u64),
+}
+
+impl Padding {
+    /// Bucket padding, refusing sizes above `MAX_PADDING_BUCKET`.
+    pub fn bucket(size: u64) -> Result<Self> {
+        let padding = Padding::Bucket(size);
+        padding.check()?;
+        Ok(padding)
+    }
+
+    pub(crate) fn check(self) -> Result<()> {
+        if let Padding::Bucket(size) = self
+            && size > MAX_PADDING_BUCKET
+        {
+            bail!("Padding bucket of {size} bytes is larger than {MAX_PADDING_BUCKET}");
+        }
+        Ok(())
+    }
+
+    /// Size of the framed payload for `len` bytes of data.
+    pub(crate) fn padded_len(self, len: u64) -> u64 {
+        let framed = 8 + len;
+        match self {
+            Padding::None => framed,
+            Padding::Padme => padme(framed),
+            Padding::Bucket(size) if size > 1 => framed.div_ceil(size) * size,
+            Padding::Bucket(_) => framed,
+        }
+    }
+}
+
+/// PADMÉ length from "Reducing Metadata Leakage from Encrypted Files and Communication with
+/// PURBs" (Nikitin et al., 2019): keep the top `floor(log2 e) + 1` bits of the length,
+/// where `e = floor(log2 len)`, and round the rest up.
+pub(crate) fn padme(len: u64) -> u64 {
+    if len < 2 {
+        return len;
+    }
+    let e = 63 - len.leading_zeros() as u64;
+    let s = 64 - e.leading_zeros() as u64;
+    let last_bits = e - s;
+    let mask = (1u64 << last_bits) - 1;
+    (len + mask) & !mask
+}
+
+pub(crate) fn pad(data: &[u8], padding: Padding) -> Vec<u8> {
+    let total = padding.padded_len(data.len() as u64) as usize;
+    let mut framed = Vec::with_capacity(total);
+    framed.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    framed.extend_from_slice(data);
+    framed.resize(total, 0);
+    framed
+}
+
+/// Strips the length prefix and padding from a decrypted payload in place.
+pub(crate) fn unpad(framed: &mut Vec<u8>) -> Result<()> {
+    if framed.len() < 8 {
+        bail!("Padded payload too short");
+    }
+    let len = u64::from_le_bytes(framed[..8].try_into().unwrap());
+    if len > (framed.len() - 8) as u64 {
+        bail!("Padded payload length out of range");
+    }
+    framed.drain(..8);
+    framed.truncate(len as usize);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padme_matches_worked_examples() {
+        // Worked out by hand from the definition
+        for (len, padded) in [
+            (0, 0),
+            (1, 1),
+            (9, 10),
+            (100, 104),
+            (1000, 1024),
+            (1025, 1088),
+            (1_000_000, 1_015_808),
+        ] {
+            assert_eq!(padme(len), padded, "padme({len})");
+        }
+    }
+
+    #[test]
+    fn padme_overhead_is_bounded() {
+        for len in (2..200_000u64).step_by(997) {
+            let padded = padme(len);
+            assert!(padded >= len);
+            assert!((padded - len) * 100 <= len * 12, "padme({len}) = {padded}");
+        }
+    }
+
+    #[test]
+    fn pad_unpad_roundtrip() {
+        for padding in [Padding::None, Padding::Padme, Padding::Bucket(4096)] {
+            let framed = pad(b"hello", padding);
+            if padding == Padding::Bucket(4096) {
+                assert_eq!(framed.len(), 4096);
+            }
+            let mut out = framed;
+            unpad(&mut out).unwrap();
+            assert_eq!(out, b"hello");
+        }
+    }
+
+    #[test]
+    fn huge_buckets_are_refused() {
+        assert_eq!(Padding::bucket(4096).unwrap(), Padding::Bucket(4096));
+        assert!(Padding::bucket(MAX_PADDING_BUCKET).is_ok());
+        assert!(Padding::bucket(MAX_PADDING_BUCKET + 1).is_err());
+        assert!(Padding::Bucket(u64::MAX).check().is_err());
+    }
+
+    #[test]
+    fn unpad_rejects_bad_length() {
+        let mut framed = pad(b"hello", Padding::None);
+        framed[0] = 6;
+        assert!(unpad(&mut framed).is_err());
+    }
+}
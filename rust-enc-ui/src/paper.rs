@@ -0,0 +1,367 @@
+//! Printable paper backups of recovery words, shares and small encrypted files.
+//!
+//! The material is encoded as `FORMAT | KIND | DATA | CHECK`, where `CHECK` is the first
+//! 4 bytes of SHA-256 over the rest. The page shows it twice: as a QR code holding
+//! `RENC-PAPER:<base32>`, and as numbered base32 lines of 32 characters for typing, each
+//! ending in a 2-character line checksum so a typo is found by line.
+//!
+//! Importing takes the typed lines, the QR text as read by any scanner app, or a photo or
+//! scan of the page (PNG or JPEG) in which the QR code is found and decoded.
+
+use crate::shamir::Share;
+use crate::{recovery, signing::write_private};
+use anyhow::{Context, Result, anyhow, bail};
+use data_encoding::BASE32_NOPAD;
+use image::GrayImage;
+use qrcode::{EcLevel, QrCode, render::svg};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const FORMAT: u8 = 1;
+const KIND_RECOVERY: u8 = 1;
+const KIND_SHARE: u8 = 2;
+const KIND_FILE: u8 = 3;
+const QR_PREFIX: &str = "RENC-PAPER:";
+const LINE_CHARS: usize = 32;
+
+/// Largest encrypted file that fits in one QR code.
+pub const MAX_FILE_LEN: usize = 2048;
+
+/// Material that can be put on paper.
+#[derive(Debug, Clone)]
+pub enum PaperBackup {
+    /// The 24 words of a recovery key.
+    RecoveryWords(Zeroizing<String>),
+    /// One `renc-share:` line.
+    Share(Zeroizing<String>),
+    /// A small encrypted file, at most `MAX_FILE_LEN` bytes.
+    File(Vec<u8>),
+}
+
+impl PaperBackup {
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            PaperBackup::RecoveryWords(_) => "recovery key",
+            PaperBackup::Share(_) => "key share",
+            PaperBackup::File(_) => "encrypted file",
+        }
+    }
+
+    fn payload(&self) -> Result<Zeroizing<Vec<u8>>> {
+        let mut out = Zeroizing::new(vec![FORMAT]);
+        match self {
+            PaperBackup::RecoveryWords(words) => {
+                out.push(KIND_RECOVERY);
+                out.extend_from_slice(recovery::parse(words)?.as_ref());
+            }
+            PaperBackup::Share(text) => {
+                let share = Share::parse(text).context("Bad share")?;
+                out.extend_from_slice(&[KIND_SHARE, share.threshold, share.count, share.index]);
+                out.extend_from_slice(share.value.as_ref());
+            }
+            PaperBackup::File(data) => {
+                if data.len() > MAX_FILE_LEN {
+                    bail!(
+                        "File is {} bytes; at most {MAX_FILE_LEN} fit on a paper backup",
+                        data.len()
+                    );
+                }
+                out.push(KIND_FILE);
+                out.extend_from_slice(data);
+            }
+        }
+        let check = Sha256::digest(&out[..]);
+        out.extend_from_slice(&check[..4]);
+        Ok(out)
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self> {
+        let Some(body_len) = payload.len().checked_sub(4).filter(|&n| n >= 2) else {
+            bail!("Paper backup is too short");
+        };
+        let (body, check) = payload.split_at(body_len);
+        if Sha256::digest(body)[..4] != *check {
+            bail!("Paper backup does not match its checksum");
+        }
+        if body[0] != FORMAT {
+            bail!("Unsupported paper backup format {}", body[0]);
+        }
+        let data = &body[2..];
+        match body[1] {
+            KIND_RECOVERY => {
+                let secret: &[u8; 32] = data
+                    .try_into()
+                    .map_err(|_| anyhow!("Malformed recovery key"))?;
+                Ok(PaperBackup::RecoveryWords(recovery::to_words(secret)))
+            }
+            KIND_SHARE => {
+                let [threshold, count, index, ref value @ ..] = *data else {
+                    bail!("Malformed share");
+                };
+                let share = Share {
+                    threshold,
+                    count,
+                    index,
+                    value: Zeroizing::new(
+                        value.try_into().map_err(|_| anyhow!("Malformed share"))?,
+                    ),
+                };
+                Ok(PaperBackup::Share(share.to_text()))
+            }
+            KIND_FILE => Ok(PaperBackup::File(data.to_vec())),
+            kind => bail!("Unknown paper backup kind {kind}"),
+        }
+    }
+
+    /// The text a QR code holds.
+    pub fn qr_text(&self) -> Result<Zeroizing<String>> {
+        let payload = self.payload()?;
+        Ok(Zeroizing::new(format!(
+            "{QR_PREFIX}{}",
+            BASE32_NOPAD.encode(&payload)
+        )))
+    }
+
+    /// Numbered lines of base32 in groups of four, each ending in its line checksum.
+    pub fn to_lines(&self) -> Result<Zeroizing<String>> {
+        let encoded = Zeroizing::new(BASE32_NOPAD.encode(&self.payload()?));
+        let mut text = Zeroizing::new(String::new());
+        for (i, chunk) in encoded.as_bytes().chunks(LINE_CHARS).enumerate() {
+            let chars = std::str::from_utf8(chunk).unwrap();
+            let groups: Vec<&str> = chunk
+                .chunks(4)
+                .map(|g| std::str::from_utf8(g).unwrap())
+                .collect();
+            text.push_str(&format!(
+                "{:02}  {}  {}\n",
+                i + 1,
+                groups.join(" "),
+                line_check(i + 1, chars)
+            ));
+        }
+        Ok(text)
+    }
+
+    fn qr_code(&self) -> Result<QrCode> {
+        QrCode::with_error_correction_level(self.qr_text()?.as_bytes(), EcLevel::M)
+            .map_err(|e| anyhow!("Cannot make a QR code: {e}"))
+    }
+
+    /// Writes a printable HTML page with the QR code and the typed lines.
+    pub fn write_page(&self, path: &Path) -> Result<()> {
+        let image = self
+            .qr_code()?
+            .render::<svg::Color>()
+            .min_dimensions(360, 360)
+            .quiet_zone(true)
+            .build();
+        let image = image
+            .split_once("?>")
+            .map_or(image.as_str(), |(_, svg)| svg);
+        let page = Zeroizing::new(format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+             <title>RENC paper backup</title></head>\n<body>\n\
+             <h1>RENC paper backup: {}</h1>\n{image}\n\
+             <p>If the QR code cannot be read, type the lines below. Spaces and case do \
+             not matter; the last two characters of each line are its checksum.</p>\n\
+             <pre style=\"font-size: 14pt\">\n{}</pre>\n</body></html>\n",
+            self.kind_name(),
+            self.to_lines()?.as_str()
+        ));
+        write_private(path, page.as_bytes()).with_context(|| format!("Writing {}", path.display()))
+    }
+
+    /// Reads a backup from typed lines or from QR text.
+    ///
+    /// Other text around the lines, such as the page heading, is ignored. `0`, `1` and `8`
+    /// are read as `O`, `I` and `B`, which base32 leaves out to avoid those mix-ups.
+    pub fn import(text: &str) -> Result<Self> {
+        let text = Zeroizing::new(text.to_uppercase());
+        if let Some(qr) = text.lines().find_map(|l| l.trim().strip_prefix(QR_PREFIX)) {
+            let payload = Zeroizing::new(
+                BASE32_NOPAD
+                    .decode(qr.trim().as_bytes())
+                    .map_err(|_| anyhow!("QR text is not valid base32"))?,
+            );
+            return Self::from_payload(&payload);
+        }
+
+        let mut encoded = Zeroizing::new(String::new());
+        let mut expected = 1;
+        for line in text.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let Some(number) = tokens.first().and_then(|t| t.parse::<usize>().ok()) else {
+                continue;
+            };
+            let [_, groups @ .., check] = tokens.as_slice() else {
+                bail!("Line {number} is incomplete");
+            };
+            if number != expected {
+                bail!("Line {expected} is missing");
+            }
+            let chars = Zeroizing::new(fix_lookalikes(&groups.concat()));
+            if line_check(number, &chars) != fix_lookalikes(check) {
+                bail!("Line {number} does not match its checksum (mistyped?)");
+            }
+            encoded.push_str(&chars);
+            expected += 1;
+        }
+        if encoded.is_empty() {
+            bail!("No backup lines found");
+        }
+        let payload = Zeroizing::new(
+            BASE32_NOPAD
+                .decode(encoded.as_bytes())
+                .map_err(|_| anyhow!("Backup lines are incomplete or not valid base32"))?,
+        );
+        Self::from_payload(&payload)
+    }
+
+    /// Reads a backup from a PNG or JPEG image holding its QR code, such as a scan of the
+    /// printed page.
+    pub fn import_image(path: &Path) -> Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("Reading {}", path.display()))?
+            .to_luma8();
+        Self::import_qr(image)
+    }
+
+    fn import_qr(image: GrayImage) -> Result<Self> {
+        let mut image = rqrr::PreparedImage::prepare(image);
+        // A photo of a desk may hold other codes; take the first that is a backup
+        for grid in image.detect_grids() {
+            let Ok((_, text)) = grid.decode() else {
+                continue;
+            };
+            let text = Zeroizing::new(text);
+            if text.starts_with(QR_PREFIX) {
+                return Self::import(&text);
+            }
+        }
+        bail!("No paper backup QR code found in the image")
+    }
+}
+
+/// Two base32 characters of SHA-256 over the line number and its characters.
+fn line_check(number: usize, chars: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"RENC paper line\0")
+        .chain_update((number as u16).to_le_bytes())
+        .chain_update(chars.as_bytes())
+        .finalize();
+    BASE32_NOPAD.encode(&digest[..2])[..2].to_string()
+}
+
+fn fix_lookalikes(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '0' => 'O',
+            '1' => 'I',
+            '8' => 'B',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share_text() -> Zeroizing<String> {
+        let shares = crate::shamir::split(
+            &[9; 32],
+            crate::ShareSplit {
+                threshold: 2,
+                count: 3,
+            },
+        )
+        .unwrap();
+        shares[2].to_text()
+    }
+
+    #[test]
+    fn lines_and_qr_roundtrip() {
+        let (_, words) = recovery::generate().unwrap();
+        for backup in [
+            PaperBackup::RecoveryWords(words.clone()),
+            PaperBackup::Share(share_text()),
+            PaperBackup::File((0..300u16).map(|i| i as u8).collect()),
+        ] {
+            let lines = backup.to_lines().unwrap();
+            let typed = format!("RENC paper backup\n\n{}", lines.to_lowercase());
+            let restored = PaperBackup::import(&typed).unwrap();
+            assert_eq!(format!("{restored:?}"), format!("{backup:?}"));
+            let restored = PaperBackup::import(&backup.qr_text().unwrap()).unwrap();
+            assert_eq!(format!("{restored:?}"), format!("{backup:?}"));
+        }
+    }
+
+    /// The QR code as a printer would put it on paper: 4 pixels a module, white margin.
+    fn print(backup: &PaperBackup) -> GrayImage {
+        let qr = backup.qr_code().unwrap();
+        let (width, colors) = (qr.width(), qr.to_colors());
+        let (scale, margin) = (4, 16);
+        let side = (width * scale + 2 * margin) as u32;
+        GrayImage::from_fn(side, side, |x, y| {
+            let module = |p: u32| (p as usize).checked_sub(margin).map(|p| p / scale);
+            let dark = match (module(x), module(y)) {
+                (Some(x), Some(y)) if x < width && y < width => {
+                    colors[y * width + x] == qrcode::Color::Dark
+                }
+                _ => false,
+            };
+            image::Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn qr_image_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, words) = recovery::generate().unwrap();
+        for backup in [
+            PaperBackup::RecoveryWords(words),
+            PaperBackup::Share(share_text()),
+            PaperBackup::File(vec![7; MAX_FILE_LEN]),
+        ] {
+            let path = dir.path().join("scan.png");
+            print(&backup).save(&path).unwrap();
+            let restored = PaperBackup::import_image(&path).unwrap();
+            assert_eq!(format!("{restored:?}"), format!("{backup:?}"));
+        }
+
+        let blank = dir.path().join("blank.png");
+        GrayImage::from_pixel(64, 64, image::Luma([255]))
+            .save(&blank)
+            .unwrap();
+        assert!(PaperBackup::import_image(&blank).is_err());
+    }
+
+    #[test]
+    fn typo_is_found_by_line() {
+        let lines = PaperBackup::Share(share_text()).to_lines().unwrap();
+        let mut typed: Vec<String> = lines.lines().map(str::to_string).collect();
+        // Swap two characters in the first group of line 2
+        let mut chars: Vec<char> = typed[1].chars().collect();
+        chars.swap(4, 5);
+        if chars[4] == chars[5] {
+            chars[4] = if chars[4] == 'A' { 'B' } else { 'A' };
+        }
+        typed[1] = chars.into_iter().collect();
+        let err = PaperBackup::import(&typed.join("\n")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 2 does not match its checksum (mistyped?)"
+        );
+
+        typed.remove(1);
+        let err = PaperBackup::import(&typed.join("\n")).unwrap_err();
+        assert_eq!(err.to_string(), "Line 2 is missing");
+    }
+
+    #[test]
+    fn oversized_file_is_refused() {
+        let backup = PaperBackup::File(vec![0; MAX_FILE_LEN + 1]);
+        assert!(backup.to_lines().is_err());
+    }
+}
@@ -0,0 +1,464 @@
+//! Reed-Solomon parity for archival storage. It follows the checksum trailer and protects
+//! everything before it, the trailer included:
+//!
+//! `PARITY_SHARDS | PARITY_HASHES (32 bytes each) | DATA_HASHES (32 bytes each) |
+//! SECTION_HASH (32) | SHARD_LEN (u32 LE) | DATA_SHARDS (u8) | PARITY_SHARDS (u8) |
+//! GROUP_COUNT (u32 LE) | HASHED_SHARDS (u32 LE) | "RPA2"`
+//!
+//! The protected data is cut into shards of `SHARD_LEN` bytes (the checksum chunk length),
+//! and each group of `DATA_SHARDS` of them gets `PARITY_SHARDS` parity shards. The last
+//! shard and the last group are zero-filled for encoding only. The shard hashes tell which
+//! shards are damaged, so every parity shard can rebuild one of them.
+//!
+//! Format 1 (`"RPAR"`, no `DATA_HASHES` or `HASHED_SHARDS`) protected only the data
+//! before the trailer and used the trailer's chunk hashes to find damage; it is still
+//! repaired, but only while its trailer is readable.
+
+use crate::checksum::Trailer;
+use anyhow::{Result, anyhow};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+
+const MAGIC: &[u8; 4] = b"RPA2";
+const MAGIC_V1: &[u8; 4] = b"RPAR";
+const FOOTER_LEN_V1: usize = 32 + 4 + 1 + 1 + 4 + 4;
+const FOOTER_LEN: usize = FOOTER_LEN_V1 + 4;
+
+/// Data shards per group.
+pub(crate) const GROUP_LEN: u8 = 32;
+
+/// Result of a repair: where damage was found, and what could not be rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RepairReport {
+    /// Damaged byte ranges of the file, in file order (data and parity shards).
+    pub damaged: Vec<Range<u64>>,
+    /// The ranges among `damaged` that had too much damage around them to rebuild.
+    pub unrepaired: Vec<Range<u64>>,
+}
+
+impl RepairReport {
+    pub fn damaged_bytes(&self) -> u64 {
+        self.damaged.iter().map(|r| r.end - r.start).sum()
+    }
+
+    pub fn repaired_bytes(&self) -> u64 {
+        self.damaged_bytes() - self.unrepaired.iter().map(|r| r.end - r.start).sum::<u64>()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.unrepaired.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Layout {
+    shard_len: usize,
+    data_shards: usize,
+    pub parity_shards: u8,
+    group_count: usize,
+    /// Data shards hashed in the section itself; zero in format 1.
+    hashed_shards: usize,
+}
+
+impl Layout {
+    fn new(protected_len: usize, shard_len: u32, parity_shards: u8) -> Self {
+        let shards = protected_len.div_ceil(shard_len as usize);
+        Self {
+            shard_len: shard_len as usize,
+            data_shards: GROUP_LEN as usize,
+            parity_shards,
+            group_count: shards.div_ceil(GROUP_LEN as usize),
+            hashed_shards: shards,
+        }
+    }
+
+    fn is_v1(&self) -> bool {
+        self.hashed_shards == 0
+    }
+
+    fn parity_count(&self) -> usize {
+        self.group_count * self.parity_shards as usize
+    }
+
+    fn footer_len(&self) -> usize {
+        if self.is_v1() {
+            FOOTER_LEN_V1
+        } else {
+            FOOTER_LEN
+        }
+    }
+
+    fn section_len(&self) -> Option<usize> {
+        self.parity_count()
+            .checked_mul(self.shard_len + 32)?
+            .checked_add(self.hashed_shards.checked_mul(32)?)?
+            .checked_add(self.footer_len())
+    }
+
+    fn codec(&self) -> Result<ReedSolomon> {
+        ReedSolomon::new(self.data_shards, self.parity_shards as usize)
+            .map_err(|e| anyhow!("Unsupported parity layout: {e:?}"))
+    }
+
+    fn footer(&self, hashes: &[u8]) -> Vec<u8> {
+        let mut params = Vec::with_capacity(self.footer_len() - 32);
+        params.extend_from_slice(&(self.shard_len as u32).to_le_bytes());
+        params.push(self.data_shards as u8);
+        params.push(self.parity_shards);
+        params.extend_from_slice(&(self.group_count as u32).to_le_bytes());
+        if self.is_v1() {
+            params.extend_from_slice(MAGIC_V1);
+        } else {
+            params.extend_from_slice(&(self.hashed_shards as u32).to_le_bytes());
+            params.extend_from_slice(MAGIC);
+        }
+
+        let mut footer = Sha256::new()
+            .chain_update(hashes)
+            .chain_update(&params)
+            .finalize()
+            .to_vec();
+        footer.extend_from_slice(&params);
+        footer
+    }
+
+    /// Shard `index` of `protected`, zero-filled to full length.
+    fn data_shard(&self, protected: &[u8], index: usize) -> Vec<u8> {
+        let mut shard = vec![0u8; self.shard_len];
+        let range = self.data_range(protected.len(), index);
+        shard[..range.len()].copy_from_slice(&protected[range]);
+        shard
+    }
+
+    /// Where data shard `index` lies in `protected_len` bytes, without the zero fill.
+    fn data_range(&self, protected_len: usize, index: usize) -> Range<usize> {
+        let start = (index * self.shard_len).min(protected_len);
+        start..(start + self.shard_len).min(protected_len)
+    }
+}
+
+/// Computes the parity section for `protected`, everything before it in the file.
+pub(crate) fn encode(protected: &[u8], shard_len: u32, parity_shards: u8) -> Result<Vec<u8>> {
+    encode_layout(
+        protected,
+        Layout::new(protected.len(), shard_len, parity_shards),
+    )
+}
+
+fn encode_layout(protected: &[u8], layout: Layout) -> Result<Vec<u8>> {
+    let codec = layout.codec()?;
+    let mut parity = Vec::with_capacity(layout.section_len().unwrap_or(0));
+    let mut hashes = Vec::with_capacity((layout.parity_count() + layout.hashed_shards) * 32);
+    for group in 0..layout.group_count {
+        let first = group * layout.data_shards;
+        let mut shards: Vec<Vec<u8>> = (first..first + layout.data_shards)
+            .map(|i| layout.data_shard(protected, i))
+            .chain((0..layout.parity_shards).map(|_| vec![0u8; layout.shard_len]))
+            .collect();
+        codec
+            .encode(&mut shards)
+            .map_err(|e| anyhow!("Parity encoding failed: {e:?}"))?;
+        for shard in &shards[layout.data_shards..] {
+            parity.extend_from_slice(shard);
+            hashes.extend_from_slice(&Sha256::digest(shard));
+        }
+    }
+    for i in 0..layout.hashed_shards {
+        hashes.extend_from_slice(&Sha256::digest(
+            &protected[layout.data_range(protected.len(), i)],
+        ));
+    }
+    let footer = layout.footer(&hashes);
+    parity.extend_from_slice(&hashes);
+    parity.extend_from_slice(&footer);
+    Ok(parity)
+}
+
+/// Finds an intact parity section at the end of `data` and returns its layout and offset.
+pub(crate) fn find(data: &[u8]) -> Option<(Layout, usize)> {
+    let footer_len = match data.get(data.len().checked_sub(4)?..)? {
+        magic if magic == MAGIC => FOOTER_LEN,
+        magic if magic == MAGIC_V1 => FOOTER_LEN_V1,
+        _ => return None,
+    };
+    let footer = &data[data.len().checked_sub(footer_len)?..];
+    let u32_at = |i: usize| u32::from_le_bytes(footer[i..i + 4].try_into().unwrap()) as usize;
+    let layout = Layout {
+        shard_len: u32_at(32),
+        data_shards: footer[36] as usize,
+        parity_shards: footer[37],
+        group_count: u32_at(38),
+        hashed_shards: if footer_len == FOOTER_LEN {
+            u32_at(42)
+        } else {
+            0
+        },
+    };
+    if layout.shard_len == 0 || (footer_len == FOOTER_LEN && layout.is_v1()) {
+        return None;
+    }
+    let start = data.len().checked_sub(layout.section_len()?)?;
+    if !layout.is_v1()
+        && layout != Layout::new(start, layout.shard_len as u32, layout.parity_shards)
+    {
+        return None;
+    }
+    let hashes = &data[hashes_start(&layout, start)..data.len() - footer_len];
+    (layout.footer(hashes) == footer).then_some((layout, start))
+}
+
+/// Offset where the parity section starts, or the file length if there is none.
+pub(crate) fn section_start(data: &[u8]) -> usize {
+    find(data).map_or(data.len(), |(_, start)| start)
+}
+
+/// File ranges of the parity shards whose hash does not match.
+pub(crate) fn damaged_shards(data: &[u8]) -> Vec<Range<u64>> {
+    let Some((layout, start)) = find(data) else {
+        return Vec::new();
+    };
+    (0..layout.parity_count())
+        .filter(|&i| !parity_shard_ok(data, &layout, start, i))
+        .map(|i| parity_range(&layout, start, i))
+        .collect()
+}
+
+fn hashes_start(layout: &Layout, start: usize) -> usize {
+    start + layout.parity_count() * layout.shard_len
+}
+
+fn parity_range(layout: &Layout, start: usize, index: usize) -> Range<u64> {
+    let offset = (start + index * layout.shard_len) as u64;
+    offset..offset + layout.shard_len as u64
+}
+
+fn parity_shard_ok(data: &[u8], layout: &Layout, start: usize, index: usize) -> bool {
+    let range = parity_range(layout, start, index);
+    let hash_at = hashes_start(layout, start) + index * 32;
+    Sha256::digest(&data[range.start as usize..range.end as usize]).as_slice()
+        == &data[hash_at..hash_at + 32]
+}
+
+fn data_shard_ok(data: &[u8], layout: &Layout, start: usize, index: usize) -> bool {
+    let hash_at = hashes_start(layout, start) + (layout.parity_count() + index) * 32;
+    Sha256::digest(&data[layout.data_range(start, index)]).as_slice()
+        == &data[hash_at..hash_at + 32]
+}
+
+/// Rebuilds damaged shards of `data` in place.
+///
+/// Returns `None` when the file has no parity section or it cannot be read, so the caller
+/// can go on without repair and leave detecting damage to authentication.
+pub(crate) fn repair(data: &mut [u8]) -> Result<Option<RepairReport>> {
+    let Some((layout, start)) = find(data) else {
+        return Ok(None);
+    };
+    let (protected, bad_data): (usize, Vec<usize>) = if layout.is_v1() {
+        let Ok((trailer, covered)) = Trailer::read(&data[..start]) else {
+            return Ok(None);
+        };
+        let check = trailer.check(&data[..covered]);
+        let expected = Layout {
+            hashed_shards: 0,
+            ..Layout::new(covered, check.chunk_len, layout.parity_shards)
+        };
+        if check.chunk_len as usize != layout.shard_len || layout != expected {
+            return Ok(None);
+        }
+        let bad = check
+            .damaged
+            .iter()
+            .map(|r| r.start as usize / layout.shard_len)
+            .collect();
+        (covered, bad)
+    } else {
+        let bad = (0..layout.hashed_shards)
+            .filter(|&i| !data_shard_ok(data, &layout, start, i))
+            .collect();
+        (start, bad)
+    };
+    let bad_parity: Vec<usize> = (0..layout.parity_count())
+        .filter(|&i| !parity_shard_ok(data, &layout, start, i))
+        .collect();
+
+    let data_range = |i: usize| {
+        let range = layout.data_range(protected, i);
+        range.start as u64..range.end as u64
+    };
+    let mut report = RepairReport {
+        damaged: bad_data.iter().map(|&i| data_range(i)).collect(),
+        unrepaired: Vec::new(),
+    };
+    report
+        .damaged
+        .extend(bad_parity.iter().map(|&i| parity_range(&layout, start, i)));
+    if report.damaged.is_empty() {
+        return Ok(Some(report));
+    }
+
+    let codec = layout.codec()?;
+    for group in 0..layout.group_count {
+        let first_data = group * layout.data_shards;
+        let first_parity = group * layout.parity_shards as usize;
+        let data_ids = first_data..first_data + layout.data_shards;
+        let parity_ids = first_parity..first_parity + layout.parity_shards as usize;
+        let group_bad_data: Vec<usize> = bad_data
+            .iter()
+            .copied()
+            .filter(|i| data_ids.contains(i))
+            .collect();
+        let group_bad_parity: Vec<usize> = bad_parity
+            .iter()
+            .copied()
+            .filter(|i| parity_ids.contains(i))
+            .collect();
+        if group_bad_data.is_empty() && group_bad_parity.is_empty() {
+            continue;
+        }
+
+        let mut shards: Vec<Option<Vec<u8>>> = data_ids
+            .clone()
+            .map(|i| {
+                (!group_bad_data.contains(&i)).then(|| layout.data_shard(&data[..protected], i))
+            })
+            .chain(parity_ids.clone().map(|i| {
+                let range = parity_range(&layout, start, i);
+                (!group_bad_parity.contains(&i))
+                    .then(|| data[range.start as usize..range.end as usize].to_vec())
+            }))
+            .collect();
+        if codec.reconstruct(&mut shards).is_err() {
+            report
+                .unrepaired
+                .extend(group_bad_data.iter().map(|&i| data_range(i)));
+            report.unrepaired.extend(
+                group_bad_parity
+                    .iter()
+                    .map(|&i| parity_range(&layout, start, i)),
+            );
+            continue;
+        }
+        for &i in &group_bad_data {
+            let range = layout.data_range(protected, i);
+            let shard = shards[i - first_data].as_ref().unwrap();
+            let len = range.len();
+            data[range].copy_from_slice(&shard[..len]);
+        }
+        for &i in &group_bad_parity {
+            let range = parity_range(&layout, start, i);
+            let shard = shards[layout.data_shards + i - first_parity]
+                .as_ref()
+                .unwrap();
+            data[range.start as usize..range.end as usize].copy_from_slice(shard);
+        }
+    }
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `len` bytes of data followed by a checksum trailer and 2 parity shards per group.
+    fn protected(len: usize) -> Vec<u8> {
+        let mut data: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+        Trailer::compute(&data, 256).write(&mut data);
+        let parity = encode(&data, 256, 2).unwrap();
+        data.extend_from_slice(&parity);
+        data
+    }
+
+    #[test]
+    fn repairs_up_to_parity_count_per_group() {
+        let clean = protected(10_000);
+        let mut data = clean.clone();
+        data[0] ^= 1;
+        data[300] ^= 1;
+        data[9_999] ^= 1; // in the second group, in a shard shared with the trailer
+        let report = repair(&mut data).unwrap().unwrap();
+        assert_eq!(report.damaged, vec![0..256, 256..512, 9_984..10_240]);
+        assert!(report.is_complete());
+        assert_eq!(report.repaired_bytes(), 3 * 256);
+        assert_eq!(data, clean);
+    }
+
+    #[test]
+    fn damaged_trailer_is_rebuilt() {
+        let clean = protected(10_000);
+        let (_, start) = find(&clean).unwrap();
+        for back in [1, 5, 40, 100] {
+            let mut data = clean.clone();
+            data[start - back] ^= 1;
+            assert!(Trailer::read(&data[..start]).is_err());
+            let report = repair(&mut data).unwrap().unwrap();
+            assert_eq!(report.damaged.len(), 1);
+            assert!(report.is_complete());
+            assert_eq!(data, clean);
+        }
+    }
+
+    #[test]
+    fn damaged_parity_is_rebuilt_too() {
+        let clean = protected(1_000);
+        let (_, start) = find(&clean).unwrap();
+        let mut data = clean.clone();
+        data[start + 10] ^= 1;
+        assert_eq!(damaged_shards(&data).len(), 1);
+        let report = repair(&mut data).unwrap().unwrap();
+        assert_eq!(report.damaged.len(), 1);
+        assert!(report.is_complete());
+        assert_eq!(data, clean);
+    }
+
+    #[test]
+    fn too_much_damage_is_reported() {
+        let mut data = protected(1_000);
+        for pos in [0, 300, 600] {
+            data[pos] ^= 1;
+        }
+        let report = repair(&mut data).unwrap().unwrap();
+        assert_eq!(report.damaged.len(), 3);
+        assert_eq!(report.unrepaired, report.damaged);
+        assert_eq!(report.repaired_bytes(), 0);
+    }
+
+    #[test]
+    fn unreadable_section_is_skipped() {
+        assert!(find(b"not protected").is_none());
+        let clean = protected(1_000);
+        // Magic, layout fields, section hash and shard hashes
+        for back in [1, 10, 30, 60, 100] {
+            let mut data = clean.clone();
+            let at = data.len() - back;
+            data[at] ^= 1;
+            assert!(find(&data).is_none(), "flip {back} bytes from the end");
+            assert!(repair(&mut data).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn format_1_sections_still_repair() {
+        let len = 10_000;
+        let mut clean: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+        Trailer::compute(&clean, 256).write(&mut clean);
+        let layout = Layout {
+            hashed_shards: 0,
+            ..Layout::new(len, 256, 2)
+        };
+        let parity = encode_layout(&clean[..len], layout).unwrap();
+        clean.extend_from_slice(&parity);
+        assert_eq!(find(&clean), Some((layout, clean.len() - parity.len())));
+
+        let mut data = clean.clone();
+        data[300] ^= 1;
+        let report = repair(&mut data).unwrap().unwrap();
+        assert_eq!(report.damaged, vec![256..512]);
+        assert_eq!(data, clean);
+
+        // Format 1 needs the trailer to find damage
+        let at = clean.len() - parity.len() - 1;
+        data[at] ^= 1;
+        assert!(repair(&mut data).unwrap().is_none());
+    }
+}
@@ -0,0 +1,141 @@
+//! Diceware-style passphrases: words drawn uniformly from the BIP39 English list (2048
+//! words, 11 bits each, unique in their first four letters) with the OS RNG.
+//!
+//! With `digit` set, one random digit is appended to one random word, adding
+//! `log2(10 × words)` bits.
+
+use anyhow::{Result, anyhow, bail};
+use bip39::Language;
+use getrandom::fill;
+use zeroize::Zeroizing;
+
+pub const MAX_WORDS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct PassphraseOptions {
+    pub words: usize,
+    pub separator: String,
+    /// Append a random digit to one of the words, for sites that insist on one.
+    pub digit: bool,
+}
+
+impl Default for PassphraseOptions {
+    /// Six words, about 66 bits.
+    fn default() -> Self {
+        Self {
+            words: 6,
+            separator: "-".to_string(),
+            digit: false,
+        }
+    }
+}
+
+/// A generated passphrase and how many bits of entropy went into it.
+pub struct Passphrase {
+    pub text: Zeroizing<String>,
+    pub entropy_bits: f64,
+}
+
+/// The entropy in bits of a passphrase generated with `opts`.
+pub fn entropy_bits(opts: &PassphraseOptions) -> f64 {
+    let words = opts.words as f64;
+    let mut bits = words * 11.0;
+    if opts.digit && opts.words > 0 {
+        bits += (10.0 * words).log2();
+    }
+    bits
+}
+
+pub fn generate(opts: &PassphraseOptions) -> Result<Passphrase> {
+    if opts.words == 0 || opts.words > MAX_WORDS {
+        bail!("Word count must be between 1 and {MAX_WORDS}");
+    }
+    if opts.separator.chars().any(char::is_alphabetic) {
+        bail!("The separator must not contain letters");
+    }
+    let list = Language::English.word_list();
+    let mut text = Zeroizing::new(String::new());
+    let digit = if opts.digit {
+        Some((below(opts.words as u32)?, below(10)?))
+    } else {
+        None
+    };
+    for i in 0..opts.words {
+        if i > 0 {
+            text.push_str(&opts.separator);
+        }
+        // 2048 is a power of two, so masking keeps the draw uniform
+        text.push_str(list[(random_u32()? & 0x7ff) as usize]);
+        if let Some((at, d)) = digit
+            && at as usize == i
+        {
+            text.push(char::from_digit(d, 10).unwrap());
+        }
+    }
+    Ok(Passphrase {
+        text,
+        entropy_bits: entropy_bits(opts),
+    })
+}
+
+fn random_u32() -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    fill(&mut bytes).map_err(|e| anyhow!("OS RNG failed for passphrase: {e}"))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// A uniform draw from `0..n`, rejecting the values that would bias it.
+fn below(n: u32) -> Result<u32> {
+    let limit = u32::MAX - u32::MAX % n;
+    loop {
+        let r = random_u32()?;
+        if r < limit {
+            return Ok(r % n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrases_follow_the_options() {
+        let opts = PassphraseOptions {
+            words: 5,
+            separator: " ".to_string(),
+            digit: true,
+        };
+        let pass = generate(&opts).unwrap();
+        let words: Vec<_> = pass.text.split(' ').collect();
+        assert_eq!(words.len(), 5);
+        let list = Language::English.word_list();
+        for word in &words {
+            assert!(list.contains(&word.trim_end_matches(|c: char| c.is_ascii_digit())));
+        }
+        assert_eq!(pass.text.chars().filter(char::is_ascii_digit).count(), 1);
+        assert!((pass.entropy_bits - (55.0 + 50f64.log2())).abs() < 1e-9);
+
+        let default = generate(&PassphraseOptions::default()).unwrap();
+        assert_eq!(default.text.split('-').count(), 6);
+        assert_eq!(default.entropy_bits, 66.0);
+        assert_ne!(
+            *default.text,
+            *generate(&PassphraseOptions::default()).unwrap().text
+        );
+    }
+
+    #[test]
+    fn bad_options_are_refused() {
+        let mut opts = PassphraseOptions {
+            words: 0,
+            ..PassphraseOptions::default()
+        };
+        assert!(generate(&opts).is_err());
+        opts.words = MAX_WORDS + 1;
+        assert!(generate(&opts).is_err());
+        opts.words = 4;
+        opts.separator = "x".to_string();
+        assert!(generate(&opts).is_err());
+    }
+}
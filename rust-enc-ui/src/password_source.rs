@@ -0,0 +1,241 @@
+//! Where a password comes from when nobody is there to type it: an environment variable,
+//! an inherited file descriptor, a file only its owner can read, or the output of a
+//! command such as `pass show backup`.
+//!
+//! Sources are written `env:NAME`, `fd:N`, `file:PATH` or `cmd:COMMAND`. From a file
+//! descriptor, file or command only the first line is the password, as `pass` stores it.
+//! Errors and `Debug` name only the kind of source, never the variable, path or command,
+//! so they are safe to log.
+
+use anyhow::{Result, anyhow, bail};
+use std::{
+    env, fmt, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use zeroize::Zeroizing;
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum PasswordSource {
+    Env(String),
+    Fd(u32),
+    File(PathBuf),
+    Command(String),
+}
+
+impl PasswordSource {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (kind, value) = spec
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Password source must start with env:, fd:, file: or cmd:"))?;
+        if value.is_empty() {
+            bail!("Password source {kind}: needs a value");
+        }
+        Ok(match kind {
+            "env" => PasswordSource::Env(value.to_string()),
+            "fd" => PasswordSource::Fd(
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("Password source fd: needs a descriptor number"))?,
+            ),
+            "file" => PasswordSource::File(PathBuf::from(value)),
+            "cmd" => PasswordSource::Command(value.to_string()),
+            _ => bail!("Password source must start with env:, fd:, file: or cmd:"),
+        })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            PasswordSource::Env(_) => "env",
+            PasswordSource::Fd(_) => "fd",
+            PasswordSource::File(_) => "file",
+            PasswordSource::Command(_) => "cmd",
+        }
+    }
+
+    /// Reads the password. Every buffer that held it is wiped.
+    pub fn read(&self) -> Result<Zeroizing<String>> {
+        let password = match self {
+            PasswordSource::Env(name) => Zeroizing::new(
+                env::var(name)
+                    .map_err(|_| anyhow!("Password environment variable is unset or not UTF-8"))?,
+            ),
+            PasswordSource::Fd(fd) => first_line(read_fd(*fd)?)?,
+            PasswordSource::File(path) => first_line(read_private(path)?)?,
+            PasswordSource::Command(command) => first_line(run(command)?)?,
+        };
+        if password.is_empty() {
+            bail!("Password from {} is empty", self.kind());
+        }
+        Ok(password)
+    }
+}
+
+impl fmt::Debug for PasswordSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PasswordSource({})", self.kind())
+    }
+}
+
+/// The first line, without its line ending.
+fn first_line(bytes: Zeroizing<Vec<u8>>) -> Result<Zeroizing<String>> {
+    let line = bytes.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let text = std::str::from_utf8(line).map_err(|_| anyhow!("Password is not UTF-8"))?;
+    Ok(Zeroizing::new(text.to_string()))
+}
+
+// Opening the descriptor through /dev/fd leaves the inherited one to the process
+#[cfg(unix)]
+fn read_fd(fd: u32) -> Result<Zeroizing<Vec<u8>>> {
+    let mut file = fs::File::open(format!("/dev/fd/{fd}"))
+        .map_err(|e| anyhow!("Opening password descriptor: {}", e.kind()))?;
+    let mut bytes = Zeroizing::new(Vec::new());
+    file.read_to_end(&mut bytes)
+        .map_err(|e| anyhow!("Reading password descriptor: {}", e.kind()))?;
+    Ok(bytes)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: u32) -> Result<Zeroizing<Vec<u8>>> {
+    bail!("Reading a password from a file descriptor needs a Unix system")
+}
+
+/// Reads a password file, refusing symlinks, anything but a regular file, and files that
+/// others could read. The checks and the read use one handle, so the file cannot be swapped
+/// in between.
+fn read_private(path: &Path) -> Result<Zeroizing<Vec<u8>>> {
+    let mut file =
+        open_private(path).map_err(|e| anyhow!("Opening password file: {}", e.kind()))?;
+    let meta = file
+        .metadata()
+        .map_err(|e| anyhow!("Reading password file: {}", e.kind()))?;
+    if !meta.is_file() {
+        bail!("Password file is not a regular file");
+    }
+    check_private(&meta)?;
+    let mut bytes = Zeroizing::new(Vec::new());
+    file.read_to_end(&mut bytes)
+        .map_err(|e| anyhow!("Reading password file: {}", e.kind()))?;
+    Ok(bytes)
+}
+
+// Not following a symlink in the last component, and not waiting for a writer on a FIFO
+#[cfg(unix)]
+fn open_private(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_private(path: &Path) -> std::io::Result<fs::File> {
+    fs::File::open(path)
+}
+
+#[cfg(unix)]
+fn check_private(meta: &fs::Metadata) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if meta.permissions().mode() & 0o077 != 0 {
+        bail!("Password file is accessible to other users; restrict it with chmod 600");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_meta: &fs::Metadata) -> Result<()> {
+    Ok(())
+}
+
+/// The standard output of `command`, run by the shell. Its standard error stays on the
+/// terminal, so tools like `pass` can still prompt there.
+fn run(command: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .map_err(|e| anyhow!("Running password command: {}", e.kind()))?;
+    let stdout = Zeroizing::new(output.stdout);
+    if !output.status.success() {
+        bail!("Password command failed ({})", output.status);
+    }
+    Ok(stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_parse_and_hide_their_details() {
+        assert_eq!(
+            PasswordSource::parse("cmd:pass show backup").unwrap(),
+            PasswordSource::Command("pass show backup".to_string())
+        );
+        assert_eq!(
+            PasswordSource::parse("fd:3").unwrap(),
+            PasswordSource::Fd(3)
+        );
+        assert!(PasswordSource::parse("fd:three").is_err());
+        assert!(PasswordSource::parse("env:").is_err());
+        assert!(PasswordSource::parse("hunter2").is_err());
+
+        let source = PasswordSource::parse("file:/secret/place").unwrap();
+        assert_eq!(format!("{source:?}"), "PasswordSource(file)");
+        let err = source.read().unwrap_err();
+        assert!(!format!("{err:#}").contains("secret"));
+    }
+
+    #[test]
+    fn first_line_is_the_password() {
+        let bytes = Zeroizing::new(b"hunter2\r\nurl: example.org\n".to_vec());
+        assert_eq!(first_line(bytes).unwrap().as_str(), "hunter2");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn password_files_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("password");
+        fs::write(&path, "hunter2\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let source = PasswordSource::File(path.clone());
+        assert!(source.read().is_err());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(source.read().unwrap().as_str(), "hunter2");
+
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        assert!(PasswordSource::File(link).read().is_err());
+        assert!(
+            PasswordSource::File(dir.path().to_path_buf())
+                .read()
+                .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_must_succeed() {
+        let command = PasswordSource::Command("printf 'hunter2\\n'".to_string());
+        assert_eq!(command.read().unwrap().as_str(), "hunter2");
+        assert!(
+            PasswordSource::Command("exit 1".to_string())
+                .read()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Public keys a file can be encrypted to, and the secret keys that decrypt it: RENC
+//! hybrid keys, or OpenSSH `ssh-ed25519` and `ssh-rsa` keys.
+
+use crate::hybrid::{self, HybridPublicKey, HybridSecretKey};
+use crate::ssh;
+use anyhow::{Context, Result, bail};
+use ssh_key::{HashAlg, PrivateKey, PublicKey};
+use std::{fmt, fs, path::Path};
+use zeroize::Zeroizing;
+
+/// A public key to encrypt to.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Recipient {
+    Hybrid(HybridPublicKey),
+    Ssh(PublicKey),
+}
+
+/// A secret key to decrypt with.
+#[derive(Clone)]
+pub enum Identity {
+    Hybrid(HybridSecretKey),
+    Ssh(Box<PrivateKey>),
+}
+
+impl Recipient {
+    /// Parses a `renc-hybrid-public:` key or an OpenSSH public key line such as the
+    /// contents of `~/.ssh/id_ed25519.pub`.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if text.starts_with(hybrid::PUBLIC_PREFIX) {
+            return Ok(Recipient::Hybrid(HybridPublicKey::from_text(text)?));
+        }
+        if text.starts_with("ssh-") || text.starts_with("ecdsa-") {
+            let key = PublicKey::from_openssh(text).context("Malformed SSH public key")?;
+            ssh::check_public_key(&key)?;
+            return Ok(Recipient::Ssh(key));
+        }
+        bail!("Not a renc-hybrid-public, ssh-ed25519 or ssh-rsa public key");
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Recipient::Hybrid(key) => key.to_text(),
+            Recipient::Ssh(key) => key
+                .to_openssh()
+                .expect("encoding a public key into memory cannot fail"),
+        }
+    }
+
+    /// Hybrid keys use `hybrid::fingerprint`; SSH keys the `SHA256:…` form `ssh-keygen -l`
+    /// prints.
+    pub fn fingerprint(&self) -> String {
+        match self {
+            Recipient::Hybrid(key) => hybrid::fingerprint(key),
+            Recipient::Ssh(key) => key.fingerprint(HashAlg::Sha256).to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Recipient({})", self.fingerprint())
+    }
+}
+
+impl From<HybridPublicKey> for Recipient {
+    fn from(key: HybridPublicKey) -> Self {
+        Recipient::Hybrid(key)
+    }
+}
+
+impl From<PublicKey> for Recipient {
+    fn from(key: PublicKey) -> Self {
+        Recipient::Ssh(key)
+    }
+}
+
+impl Identity {
+    /// The recipient this key decrypts for.
+    pub fn recipient(&self) -> Recipient {
+        match self {
+            Identity::Hybrid(key) => Recipient::Hybrid(key.public_key()),
+            Identity::Ssh(key) => Recipient::Ssh(key.public_key().clone()),
+        }
+    }
+
+    /// Parses the contents of a key file, as `read_identity` does.
+    pub fn from_text(text: &str, passphrase: Option<&str>) -> Result<Self> {
+        if text.trim_start().starts_with(hybrid::SECRET_PREFIX) {
+            return Ok(Identity::Hybrid(HybridSecretKey::from_text(text)?));
+        }
+        Ok(Identity::Ssh(Box::new(ssh::read_private_key(
+            text, passphrase,
+        )?)))
+    }
+
+    /// The unprotected key file contents, for storing the key encrypted elsewhere.
+    pub(crate) fn to_text(&self) -> Zeroizing<String> {
+        match self {
+            Identity::Hybrid(key) => key.to_text(),
+            Identity::Ssh(key) => key
+                .to_openssh(ssh_key::LineEnding::LF)
+                .expect("encoding a private key into memory cannot fail"),
+        }
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Identity({})", self.recipient().fingerprint())
+    }
+}
+
+impl From<HybridSecretKey> for Identity {
+    fn from(key: HybridSecretKey) -> Self {
+        Identity::Hybrid(key)
+    }
+}
+
+impl From<PrivateKey> for Identity {
+    fn from(key: PrivateKey) -> Self {
+        Identity::Ssh(Box::new(key))
+    }
+}
+
+pub fn read_recipient(path: &Path) -> Result<Recipient> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Reading public key {}", path.display()))?;
+    Recipient::from_text(&text).with_context(|| path.display().to_string())
+}
+
+/// Reads a `renc-hybrid-secret:` key file or an OpenSSH private key. A protected SSH key
+/// needs `passphrase`; without it this fails with `Error::KeyPassphraseRequired`.
+pub fn read_identity(path: &Path, passphrase: Option<&str>) -> Result<Identity> {
+    let text = Zeroizing::new(
+        fs::read_to_string(path)
+            .with_context(|| format!("Reading secret key {}", path.display()))?,
+    );
+    Identity::from_text(&text, passphrase).with_context(|| path.display().to_string())
+}
+
+/// Whether `path` is an OpenSSH private key protected by a passphrase, so the GUI knows
+/// to ask for one.
+pub fn needs_passphrase(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| PrivateKey::from_openssh(Zeroizing::new(text).as_str()).ok())
+        .is_some_and(|key| key.is_encrypted())
+}
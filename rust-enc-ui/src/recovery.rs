@@ -0,0 +1,113 @@
+//! Recovery keys: a random 256-bit secret shown once as a 24-word BIP39 mnemonic (English
+//! wordlist, with the standard 8-bit checksum in the last word).
+//!
+//! The secret has full entropy, so the key that wraps the data key is derived with HKDF
+//! rather than Argon2.
+
+use anyhow::{Result, anyhow, bail};
+use bip39::{Language, Mnemonic};
+use getrandom::fill;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+pub const WORD_COUNT: usize = 24;
+
+/// Draws a new recovery secret and returns it with its mnemonic.
+pub(crate) fn generate() -> Result<(Zeroizing<[u8; 32]>, Zeroizing<String>)> {
+    let mut secret = Zeroizing::new([0u8; 32]);
+    fill(secret.as_mut()).map_err(|e| anyhow!("OS RNG failed for recovery key: {e}"))?;
+    let words = to_words(&secret);
+    Ok((secret, words))
+}
+
+/// The mnemonic for a recovery secret.
+pub(crate) fn to_words(secret: &[u8; 32]) -> Zeroizing<String> {
+    let mnemonic =
+        Mnemonic::from_entropy(secret).expect("32 bytes is a valid BIP39 entropy length");
+    Zeroizing::new(mnemonic.words().collect::<Vec<_>>().join(" "))
+}
+
+/// Recovers the secret from typed words. Case and spacing do not matter; errors name the
+/// first misspelled word, or report a checksum mismatch for valid but wrong words.
+pub(crate) fn parse(text: &str) -> Result<Zeroizing<[u8; 32]>> {
+    check_words(text)?;
+    let normalized = Zeroizing::new(text.to_lowercase());
+    let mnemonic = Mnemonic::parse_in_normalized(Language::English, &normalized)
+        .map_err(|e| anyhow!("Bad recovery words: {e}"))?;
+    let (entropy, len) = mnemonic.to_entropy_array();
+    let entropy = Zeroizing::new(entropy);
+    Ok(Zeroizing::new(entropy[..len].try_into().unwrap()))
+}
+
+/// The key-encryption key for a recovery slot.
+pub(crate) fn derive_kek(secret: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+    let mut kek = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, secret)
+        .expand(b"RENC recovery key", kek.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    kek
+}
+
+/// Checks typed recovery words without needing the file, for feedback while typing.
+///
+/// Fails on the first word that is not in the wordlist, suggesting words with the same
+/// first four letters (which are unique in the BIP39 list), then on a wrong word count or
+/// a checksum mismatch.
+pub fn check_words(text: &str) -> Result<()> {
+    let text = Zeroizing::new(text.to_lowercase());
+    let words: Vec<&str> = text.split_whitespace().collect();
+    for (i, word) in words.iter().enumerate() {
+        if Language::English.find_word(word).is_none() {
+            let prefix: String = word.chars().take(4).collect();
+            match Language::English.words_by_prefix(&prefix) {
+                [] => bail!("Word {} \"{word}\" is not in the wordlist", i + 1),
+                close => bail!(
+                    "Word {} \"{word}\" is not in the wordlist (did you mean {}?)",
+                    i + 1,
+                    close.join(", ")
+                ),
+            }
+        }
+    }
+    if words.len() != WORD_COUNT {
+        bail!("Expected {WORD_COUNT} words, got {}", words.len());
+    }
+    let joined = Zeroizing::new(words.join(" "));
+    if Mnemonic::parse_in_normalized(Language::English, &joined).is_err() {
+        bail!("Recovery words do not match their checksum (a word is wrong or out of order)");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_roundtrip() {
+        let (secret, words) = generate().unwrap();
+        assert_eq!(words.split(' ').count(), WORD_COUNT);
+        assert_eq!(*parse(&words).unwrap(), *secret);
+        // Case and spacing are forgiven
+        let sloppy = format!("  {}\n", words.to_uppercase().replace(' ', "   "));
+        assert_eq!(*parse(&sloppy).unwrap(), *secret);
+    }
+
+    #[test]
+    fn typos_are_caught() {
+        let words = Mnemonic::from_entropy(&[0u8; 32]).unwrap().to_string();
+        // "abandon" x23 + "art"
+        let err = check_words(&words.replacen("abandon", "abandn", 1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Word 1 \"abandn\" is not in the wordlist (did you mean abandon?)"
+        );
+        let err = check_words(&words.replacen("abandon ", "", 1)).unwrap_err();
+        assert!(err.to_string().contains("got 23"), "{err}");
+        let swapped = words.replace(" art", "").replacen("abandon", "art", 1) + " abandon";
+        let err = check_words(&swapped).unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{err}");
+        assert!(parse(&swapped).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Shamir's secret sharing over GF(2^8) for threshold unlock, and the printable share
+//! format `renc-share:THRESHOLD:COUNT:INDEX:VALUE CHECKSUM` (value and checksum in hex).
+//!
+//! Each byte of the 32-byte secret is the constant term of its own random polynomial of
+//! degree `THRESHOLD - 1`; share `INDEX` holds the polynomials evaluated at `INDEX`.
+
+use anyhow::{Result, anyhow, bail};
+use getrandom::fill;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+const PREFIX: &str = "renc-share:";
+
+/// How to split the file key: any `threshold` of `count` shares unlock the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShareSplit {
+    pub threshold: u8,
+    pub count: u8,
+}
+
+pub(crate) struct Share {
+    pub threshold: u8,
+    pub count: u8,
+    /// The x coordinate, from 1.
+    pub index: u8,
+    pub value: Zeroizing<[u8; 32]>,
+}
+
+impl Share {
+    /// Stored in the key slot so a wrong share can be named before combining.
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(b"RENC share\0")
+            .chain_update([self.index])
+            .chain_update(self.value.as_slice())
+            .finalize()
+            .into()
+    }
+
+    /// Catches typos in a transcribed share.
+    fn checksum(&self) -> [u8; 4] {
+        let digest = Sha256::new()
+            .chain_update(b"RENC share checksum\0")
+            .chain_update([self.threshold, self.count, self.index])
+            .chain_update(self.value.as_slice())
+            .finalize();
+        digest[..4].try_into().unwrap()
+    }
+
+    pub fn to_text(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "{PREFIX}{}:{}:{}:{}{}",
+            self.threshold,
+            self.count,
+            self.index,
+            hex::encode(self.value.as_slice()),
+            hex::encode(self.checksum())
+        ))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let Some(rest) = text.trim().strip_prefix(PREFIX) else {
+            bail!("not a share (expected {PREFIX}…)");
+        };
+        let fields: Vec<&str> = rest.split(':').collect();
+        let [threshold, count, index, encoded] = fields[..] else {
+            bail!("wrong number of fields");
+        };
+        let number = |s: &str| s.parse::<u8>().map_err(|_| anyhow!("bad number {s:?}"));
+        let mut bytes = Zeroizing::new([0u8; 36]);
+        hex::decode_to_slice(encoded, bytes.as_mut()).map_err(|_| anyhow!("bad hex value"))?;
+        let share = Self {
+            threshold: number(threshold)?,
+            count: number(count)?,
+            index: number(index)?,
+            value: Zeroizing::new(bytes[..32].try_into().unwrap()),
+        };
+        if share.checksum() != bytes[32..] {
+            bail!("checksum mismatch (mistyped?)");
+        }
+        if share.index == 0 || share.index > share.count || share.threshold > share.count {
+            bail!("inconsistent share numbers");
+        }
+        Ok(share)
+    }
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    // Carry-less multiply modulo x^8 + x^4 + x^3 + x + 1, without data-dependent branches
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        a = (a << 1) ^ (0x1b & (a >> 7).wrapping_neg());
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_inv(a: u8) -> u8 {
+    // a^254 = a^-1 for a != 0
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+pub(crate) fn split(secret: &[u8; 32], how: ShareSplit) -> Result<Vec<Share>> {
+    if how.threshold == 0 || how.threshold > how.count {
+        bail!(
+            "Cannot split into {} shares with a threshold of {}",
+            how.count,
+            how.threshold
+        );
+    }
+    let mut coefficients = Zeroizing::new(vec![[0u8; 32]; how.threshold as usize]);
+    coefficients[0] = *secret;
+    for c in &mut coefficients[1..] {
+        fill(c).map_err(|e| anyhow!("OS RNG failed for shares: {e}"))?;
+    }
+
+    Ok((1..=how.count)
+        .map(|x| {
+            let mut value = Zeroizing::new([0u8; 32]);
+            for (i, byte) in value.iter_mut().enumerate() {
+                // Horner's rule from the highest coefficient down
+                *byte = coefficients
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, c| gf_mul(acc, x) ^ c[i]);
+            }
+            Share {
+                threshold: how.threshold,
+                count: how.count,
+                index: x,
+                value,
+            }
+        })
+        .collect())
+}
+
+/// Recovers the secret from shares with distinct indices, at least `threshold` of them.
+pub(crate) fn combine(shares: &[&Share]) -> Zeroizing<[u8; 32]> {
+    let mut secret = Zeroizing::new([0u8; 32]);
+    for (j, share) in shares.iter().enumerate() {
+        // Lagrange basis polynomial for this share, evaluated at 0
+        let mut basis = 1;
+        for (m, other) in shares.iter().enumerate() {
+            if m != j {
+                basis = gf_mul(
+                    basis,
+                    gf_mul(other.index, gf_inv(other.index ^ share.index)),
+                );
+            }
+        }
+        for (s, y) in secret.iter_mut().zip(share.value.iter()) {
+            *s ^= gf_mul(basis, *y);
+        }
+    }
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gf_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1, "{a}");
+        }
+        // The AES field's worked example: {57} x {83} = {c1}
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+    }
+
+    #[test]
+    fn any_threshold_subset_recovers() {
+        let secret = [0x42u8; 32];
+        let how = ShareSplit {
+            threshold: 3,
+            count: 5,
+        };
+        let shares = split(&secret, how).unwrap();
+        for (a, b, c) in [(0, 1, 2), (0, 2, 4), (1, 3, 4), (4, 2, 0)] {
+            let subset = [&shares[a], &shares[b], &shares[c]];
+            assert_eq!(*combine(&subset), secret);
+        }
+        // Two shares give an unrelated value
+        assert_ne!(*combine(&[&shares[0], &shares[1]]), secret);
+    }
+
+    #[test]
+    fn text_roundtrip_and_typo_detection() {
+        let shares = split(
+            &[7; 32],
+            ShareSplit {
+                threshold: 2,
+                count: 3,
+            },
+        )
+        .unwrap();
+        let text = shares[1].to_text();
+        let parsed = Share::parse(&text).unwrap();
+        assert_eq!((parsed.threshold, parsed.count, parsed.index), (2, 3, 2));
+        assert_eq!(*parsed.value, *shares[1].value);
+
+        let mut typo = text.to_string();
+        let last = typo.pop().unwrap();
+        typo.push(if last == '0' { '1' } else { '0' });
+        assert!(Share::parse(&typo).is_err());
+    }
+
+    #[test]
+    fn bad_split_parameters() {
+        for (threshold, count) in [(0, 3), (4, 3)] {
+            assert!(split(&[0; 32], ShareSplit { threshold, count }).is_err());
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Ed25519 sign-then-encrypt.
+//!
+//! The sender signs the plaintext hash together with the file header, and the
+//! `PUBLIC_KEY | SIGNATURE` block is appended to the plaintext before encryption, so
+//! only someone who can decrypt learns who signed.
+
+use crate::error::Error;
+use anyhow::{Context, Result, anyhow, bail};
+use ed25519_dalek::{Signature, Signer as _};
+use getrandom::fill;
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path};
+use zeroize::Zeroizing;
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// `PUBLIC_KEY (32) | SIGNATURE (64)`, appended to the signed plaintext.
+pub(crate) const BLOCK_LEN: usize = 32 + 64;
+
+const SECRET_PREFIX: &str = "renc-ed25519-secret:";
+const PUBLIC_PREFIX: &str = "renc-ed25519-public:";
+
+/// Who signed a decrypted file.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    pub public_key: VerifyingKey,
+    pub fingerprint: String,
+    /// Whether the key is in `DecryptOptions::trusted_signers`.
+    pub trusted: bool,
+}
+
+pub fn generate_signing_key() -> Result<SigningKey> {
+    let mut seed = Zeroizing::new([0u8; 32]);
+    fill(seed.as_mut()).map_err(|e| anyhow!("OS RNG failed for signing key: {e}"))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Short name for a signing key, reported in `Signer::fingerprint`.
+pub fn fingerprint(key: &VerifyingKey) -> String {
+    crate::fingerprint::of(key.as_bytes())
+}
+
+pub fn write_signing_key(path: &Path, key: &SigningKey) -> Result<()> {
+    let text = Zeroizing::new(format!("{SECRET_PREFIX}{}\n", hex::encode(key.to_bytes())));
+    write_private(path, text.as_bytes())
+        .with_context(|| format!("Writing signing key {}", path.display()))
+}
+
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let text = Zeroizing::new(
+        fs::read_to_string(path)
+            .with_context(|| format!("Reading signing key {}", path.display()))?,
+    );
+    let seed = Zeroizing::new(decode_key(&text, SECRET_PREFIX)?);
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+pub fn write_verifying_key(path: &Path, key: &VerifyingKey) -> Result<()> {
+    fs::write(
+        path,
+        format!("{PUBLIC_PREFIX}{}\n", hex::encode(key.as_bytes())),
+    )
+    .with_context(|| format!("Writing public key {}", path.display()))
+}
+
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Reading public key {}", path.display()))?;
+    VerifyingKey::from_bytes(&decode_key(&text, PUBLIC_PREFIX)?)
+        .map_err(|_| anyhow!("{} is not a valid Ed25519 public key", path.display()))
+}
+
+fn decode_key(text: &str, prefix: &str) -> Result<[u8; 32]> {
+    let Some(encoded) = text.trim().strip_prefix(prefix) else {
+        bail!("Not a {} key file", prefix.trim_end_matches(':'));
+    };
+    let mut key = [0u8; 32];
+    hex::decode_to_slice(encoded, &mut key).map_err(|_| anyhow!("Malformed key file"))?;
+    Ok(key)
+}
+
+#[cfg(unix)]
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    f.write_all(contents)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
+
+/// What gets signed: a domain label, the authenticated header bytes and the plaintext hash.
+fn message(header_aad: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut msg = b"RENC signature\0".to_vec();
+    msg.extend_from_slice(header_aad);
+    msg.extend_from_slice(&Sha256::digest(data));
+    msg
+}
+
+pub(crate) fn sign(key: &SigningKey, header_aad: &[u8], data: &[u8]) -> [u8; BLOCK_LEN] {
+    let signature = key.sign(&message(header_aad, data));
+    let mut block = [0u8; BLOCK_LEN];
+    block[..32].copy_from_slice(key.verifying_key().as_bytes());
+    block[32..].copy_from_slice(&signature.to_bytes());
+    block
+}
+
+/// Checks a signature block and returns the signer's public key.
+pub(crate) fn verify(
+    block: &[u8; BLOCK_LEN],
+    header_aad: &[u8],
+    data: &[u8],
+) -> Result<VerifyingKey, Error> {
+    let key = VerifyingKey::from_bytes(block[..32].try_into().unwrap())
+        .map_err(|_| Error::BadSignature)?;
+    let signature = Signature::from_bytes(block[32..].try_into().unwrap());
+    key.verify_strict(&message(header_aad, data), &signature)
+        .map_err(|_| Error::BadSignature)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_verify_binds_header_and_data() {
+        let key = generate_signing_key().unwrap();
+        let block = sign(&key, b"header", b"data");
+        assert_eq!(
+            verify(&block, b"header", b"data").unwrap(),
+            key.verifying_key()
+        );
+        assert!(verify(&block, b"other header", b"data").is_err());
+        assert!(verify(&block, b"header", b"other data").is_err());
+    }
+
+    #[test]
+    fn key_files_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = generate_signing_key().unwrap();
+        write_signing_key(&dir.path().join("id"), &key).unwrap();
+        write_verifying_key(&dir.path().join("id.pub"), &key.verifying_key()).unwrap();
+
+        let secret = read_signing_key(&dir.path().join("id")).unwrap();
+        assert_eq!(secret.to_bytes(), key.to_bytes());
+        let public = read_verifying_key(&dir.path().join("id.pub")).unwrap();
+        assert_eq!(public, key.verifying_key());
+        // A public key file is not accepted as a secret key
+        assert!(read_signing_key(&dir.path().join("id.pub")).is_err());
+    }
+
+    #[test]
+    fn fingerprint_format() {
+        let key = SigningKey::from_bytes(&[1; 32]).verifying_key();
+        let fp = fingerprint(&key);
+        assert_eq!(fp.len(), 8 * 4 + 7);
+        assert_eq!(fp.split(':').count(), 8);
+    }
+}
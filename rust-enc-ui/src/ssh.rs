@@ -0,0 +1,225 @@
+//! Existing OpenSSH keys as recipients, so users need not manage a second kind of key.
+//!
+//! `ssh-ed25519`: the Ed25519 public key is converted to its X25519 form and the wrapping
+//! key is `HKDF-SHA256(salt = LABEL, ikm = X25519(ephemeral, recipient),
+//! info = EPHEMERAL | RECIPIENT_X25519 | SHA-256(KEY_BLOB))`; the slot carries the
+//! ephemeral public key.
+//!
+//! `ssh-rsa`: a random wrapping key is encrypted with RSA-OAEP-SHA256 under a fixed label;
+//! the slot carries the OAEP ciphertext. Keys shorter than 2048 bits are refused.
+//!
+//! Each slot is tagged with the first 4 bytes of SHA-256 over the public key blob, so a
+//! secret key only tries the slots made for it.
+
+use crate::Error;
+use anyhow::{Context, Result, anyhow, bail};
+use getrandom::fill;
+use hkdf::Hkdf;
+use rand_core::OsRng;
+use rsa::{Oaep, traits::PublicKeyParts};
+use sha2::{Digest, Sha256};
+use ssh_key::{PrivateKey, PublicKey, private::KeypairData, public::KeyData};
+use x25519_dalek::StaticSecret;
+use zeroize::Zeroizing;
+
+const ED25519_LABEL: &[u8] = b"RENC ssh-ed25519";
+const RSA_LABEL: &str = "RENC ssh-rsa";
+const MIN_RSA_BITS: usize = 2048;
+
+pub(crate) const TAG_LEN: usize = 4;
+
+/// The slot tag for `key`.
+pub(crate) fn tag(key: &PublicKey) -> [u8; TAG_LEN] {
+    Sha256::digest(key_blob(key))[..TAG_LEN].try_into().unwrap()
+}
+
+fn key_blob(key: &PublicKey) -> Vec<u8> {
+    key.to_bytes()
+        .expect("encoding a public key into memory cannot fail")
+}
+
+/// Fails for key types and sizes that cannot be recipients.
+pub(crate) fn check_public_key(key: &PublicKey) -> Result<()> {
+    match key.key_data() {
+        KeyData::Ed25519(_) => Ok(()),
+        KeyData::Rsa(rsa) => {
+            let rsa = rsa::RsaPublicKey::try_from(rsa).map_err(|_| anyhow!("Invalid RSA key"))?;
+            let bits = rsa.n().bits();
+            if bits < MIN_RSA_BITS {
+                bail!("RSA key has {bits} bits; at least {MIN_RSA_BITS} are required");
+            }
+            Ok(())
+        }
+        _ => bail!(
+            "Unsupported SSH key type {}; use ssh-ed25519 or ssh-rsa",
+            key.algorithm()
+        ),
+    }
+}
+
+fn derive_ed25519(
+    shared: &[u8; 32],
+    ephemeral: &[u8; 32],
+    recipient: &[u8; 32],
+    blob: &[u8],
+) -> Zeroizing<[u8; 32]> {
+    let mut info = ephemeral.to_vec();
+    info.extend_from_slice(recipient);
+    info.extend_from_slice(&Sha256::digest(blob));
+    let mut kek = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(ED25519_LABEL), shared)
+        .expand(&info, kek.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    kek
+}
+
+fn montgomery(key: &ssh_key::public::Ed25519PublicKey) -> Result<[u8; 32]> {
+    let key =
+        ed25519_dalek::VerifyingKey::try_from(key).map_err(|_| anyhow!("Invalid Ed25519 key"))?;
+    Ok(key.to_montgomery().to_bytes())
+}
+
+/// A fresh wrapping key and the bytes the recipient needs to recover it.
+pub(crate) fn encapsulate(recipient: &PublicKey) -> Result<(Zeroizing<[u8; 32]>, Vec<u8>)> {
+    check_public_key(recipient)?;
+    match recipient.key_data() {
+        KeyData::Ed25519(key) => {
+            let recipient_x = montgomery(key)?;
+            let mut random = Zeroizing::new([0u8; 32]);
+            fill(random.as_mut()).map_err(|e| anyhow!("OS RNG failed for encapsulation: {e}"))?;
+            let secret = StaticSecret::from(*random);
+            let ephemeral = x25519_dalek::PublicKey::from(&secret).to_bytes();
+            let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(recipient_x));
+            if !shared.was_contributory() {
+                bail!("Recipient has an invalid Ed25519 key");
+            }
+            let kek = derive_ed25519(
+                shared.as_bytes(),
+                &ephemeral,
+                &recipient_x,
+                &key_blob(recipient),
+            );
+            Ok((kek, ephemeral.to_vec()))
+        }
+        KeyData::Rsa(key) => {
+            let key = rsa::RsaPublicKey::try_from(key).map_err(|_| anyhow!("Invalid RSA key"))?;
+            let mut kek = Zeroizing::new([0u8; 32]);
+            fill(kek.as_mut()).map_err(|e| anyhow!("OS RNG failed for encapsulation: {e}"))?;
+            let ciphertext = key
+                .encrypt(
+                    &mut OsRng,
+                    Oaep::new_with_label::<Sha256, _>(RSA_LABEL),
+                    kek.as_ref(),
+                )
+                .map_err(|e| anyhow!("RSA encryption failed: {e}"))?;
+            Ok((kek, ciphertext))
+        }
+        _ => unreachable!("checked above"),
+    }
+}
+
+/// The wrapping key `identity` recovers from a slot made for it, or `None` when the slot
+/// does not fit the key.
+pub(crate) fn decapsulate(
+    identity: &PrivateKey,
+    encapsulated: &[u8],
+) -> Option<Zeroizing<[u8; 32]>> {
+    match identity.key_data() {
+        KeypairData::Ed25519(keypair) => {
+            let ephemeral: [u8; 32] = encapsulated.try_into().ok()?;
+            let signing = ed25519_dalek::SigningKey::from(&keypair.private);
+            let secret = StaticSecret::from(signing.to_scalar_bytes());
+            let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(ephemeral));
+            let recipient_x = montgomery(&keypair.public).ok()?;
+            Some(derive_ed25519(
+                shared.as_bytes(),
+                &ephemeral,
+                &recipient_x,
+                &key_blob(identity.public_key()),
+            ))
+        }
+        KeypairData::Rsa(keypair) => {
+            let key = rsa_private_key(keypair)?;
+            // Blinded, so the timing of the private-key operation says nothing about it
+            let kek = Zeroizing::new(
+                key.decrypt_blinded(
+                    &mut OsRng,
+                    Oaep::new_with_label::<Sha256, _>(RSA_LABEL),
+                    encapsulated,
+                )
+                .ok()?,
+            );
+            Some(Zeroizing::new(kek.as_slice().try_into().ok()?))
+        }
+        _ => None,
+    }
+}
+
+// ssh-key 0.6 has a `TryFrom` for this, but it passes the prime `p` twice
+fn rsa_private_key(keypair: &ssh_key::private::RsaKeypair) -> Option<rsa::RsaPrivateKey> {
+    let uint = |n: &ssh_key::Mpint| n.as_positive_bytes().map(rsa::BigUint::from_bytes_be);
+    rsa::RsaPrivateKey::from_components(
+        uint(&keypair.public.n)?,
+        uint(&keypair.public.e)?,
+        uint(&keypair.private.d)?,
+        vec![uint(&keypair.private.p)?, uint(&keypair.private.q)?],
+    )
+    .ok()
+}
+
+/// Reads an OpenSSH private key, decrypting it with `passphrase` when it is protected.
+pub(crate) fn read_private_key(text: &str, passphrase: Option<&str>) -> Result<PrivateKey> {
+    let key = PrivateKey::from_openssh(text).context("Not an OpenSSH private key")?;
+    let key = if key.is_encrypted() {
+        let Some(passphrase) = passphrase else {
+            return Err(Error::KeyPassphraseRequired.into());
+        };
+        key.decrypt(passphrase)
+            .map_err(|_| Error::WrongKeyPassphrase)?
+    } else {
+        key
+    };
+    check_public_key(key.public_key())?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssh_key::Algorithm;
+
+    #[test]
+    fn ed25519_encapsulation_roundtrip() {
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let (kek, encapsulated) = encapsulate(key.public_key()).unwrap();
+        assert_eq!(*decapsulate(&key, &encapsulated).unwrap(), *kek);
+        let other = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        assert_ne!(*decapsulate(&other, &encapsulated).unwrap(), *kek);
+        assert_ne!(tag(key.public_key()), tag(other.public_key()));
+    }
+
+    #[test]
+    fn protected_keys_need_their_passphrase() {
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let text = key.to_openssh(ssh_key::LineEnding::LF).unwrap();
+        assert!(read_private_key(&text, None).is_ok());
+        let encrypted = key.encrypt(&mut OsRng, "hunter2").unwrap();
+        let text = encrypted.to_openssh(ssh_key::LineEnding::LF).unwrap();
+        let err = read_private_key(&text, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::KeyPassphraseRequired)
+        ));
+        let err = read_private_key(&text, Some("wrong")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::WrongKeyPassphrase)
+        ));
+        assert_eq!(
+            read_private_key(&text, Some("hunter2"))
+                .unwrap()
+                .public_key(),
+            key.public_key()
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Password strength estimates in the style of zxcvbn.
+//!
+//! The password is split into the guessable patterns an attacker tries first: common
+//! passwords, dictionary words (the BIP39 English list), sequences, repeats, keyboard rows
+//! and years, each with a guess count. The rest is brute-forced at 10 guesses per
+//! character. The estimate is the cheapest such split, multiplied by the number of ways to
+//! order its parts, and the score uses zxcvbn's thresholds.
+
+use bip39::Language;
+
+/// Guesses per second assumed for the crack time: an offline attack on a slow hash such as
+/// Argon2 with many GPUs.
+const GUESSES_PER_SECOND: f64 = 1e4;
+/// Longest prefix that is analysed; the rest counts as brute force.
+const MAX_ANALYSED: usize = 64;
+
+/// The most common passwords from public breach corpora, most common first, separated by
+/// spaces.
+const COMMON: &str = "\
+    123456 password 12345678 qwerty 123456789 12345 1234 111111 1234567 dragon \
+    123123 baseball abc123 football monkey letmein 696969 shadow master 666666 \
+    qwertyuiop 123321 mustang 1234567890 michael 654321 superman 1qaz2wsx 7777777 \
+    121212 000000 qazwsx 123qwe killer trustno1 jordan jennifer zxcvbnm asdfgh \
+    hunter buster soccer harley batman andrew tigger sunshine iloveyou 2000 charlie \
+    robert thomas hockey ranger daniel starwars klaster 112233 george computer \
+    michelle jessica pepper 1111 zxcvbn 555555 11111111 131313 freedom 777777 pass \
+    maggie 159753 aaaaaa ginger princess joshua cheese amanda summer love ashley \
+    nicole chelsea biteme matthew access yankees 987654321 dallas austin thunder \
+    taylor matrix admin welcome login passw0rd hello secret whatever qwerty123 \
+    password1 changeme";
+
+const KEYBOARD_ROWS: &[&str] = &[
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+/// Result of `estimate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// 0 (too guessable) to 4 (very unguessable), as in zxcvbn.
+    pub score: u8,
+    /// Estimated guesses needed, as a power of ten.
+    pub guesses_log10: f64,
+    /// What makes the password weak, if anything does.
+    pub warning: Option<String>,
+    /// How to improve it.
+    pub suggestions: Vec<String>,
+}
+
+impl Strength {
+    pub fn label(&self) -> &'static str {
+        ["very weak", "weak", "fair", "strong", "very strong"][self.score as usize]
+    }
+
+    /// Time to guess the password in an offline attack at 10⁴ guesses per second.
+    pub fn crack_time(&self) -> String {
+        let seconds = 10f64.powf(self.guesses_log10) / GUESSES_PER_SECOND;
+        let units = [
+            (60.0 * 60.0 * 24.0 * 365.0 * 100.0, "century", "centuries"),
+            (60.0 * 60.0 * 24.0 * 365.0, "year", "years"),
+            (60.0 * 60.0 * 24.0 * 31.0, "month", "months"),
+            (60.0 * 60.0 * 24.0, "day", "days"),
+            (60.0 * 60.0, "hour", "hours"),
+            (60.0, "minute", "minutes"),
+            (1.0, "second", "seconds"),
+        ];
+        if seconds < 1.0 {
+            return "less than a second".to_string();
+        }
+        if seconds >= units[0].0 * 10.0 {
+            return "centuries".to_string();
+        }
+        let (unit, one, many) = units
+            .into_iter()
+            .find(|(unit, ..)| seconds >= *unit)
+            .unwrap();
+        match (seconds / unit).round() as u64 {
+            1 => format!("1 {one}"),
+            n => format!("{n} {many}"),
+        }
+    }
+
+    /// The warning and suggestions as one message.
+    pub fn advice(&self) -> String {
+        self.warning
+            .iter()
+            .chain(&self.suggestions)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(". ")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    Common,
+    Word,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Year,
+    BruteForce,
+}
+
+#[derive(Debug, Clone)]
+struct Match {
+    start: usize,
+    end: usize,
+    pattern: Pattern,
+    guesses_log10: f64,
+    uppercase: bool,
+    substituted: bool,
+}
+
+/// Estimates how many guesses `password` takes.
+pub fn estimate(password: &str) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+    let analysed = &chars[..chars.len().min(MAX_ANALYSED)];
+    let (mut guesses_log10, sequence) = cheapest_split(analysed);
+    guesses_log10 += (chars.len() - analysed.len()) as f64;
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    let (warning, suggestions) = feedback(score, &sequence, chars.len());
+    Strength {
+        score,
+        guesses_log10,
+        warning,
+        suggestions,
+    }
+}
+
+fn cheapest_split(chars: &[char]) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    if n == 0 {
+        return (0.0, Vec::new());
+    }
+    let matches = find_matches(chars);
+
+    // best[k][c]: cheapest cover of chars[..k] by c parts: log10 of the guess product and
+    // the last part
+    let mut best: Vec<Vec<Option<(f64, Match)>>> = vec![vec![None; n + 1]; n + 1];
+    for end in 1..=n {
+        let candidates = matches
+            .iter()
+            .filter(|m| m.end == end)
+            .cloned()
+            .chain((0..end).map(|start| Match {
+                start,
+                end,
+                pattern: Pattern::BruteForce,
+                guesses_log10: (end - start) as f64,
+                uppercase: false,
+                substituted: false,
+            }));
+        for m in candidates {
+            for count in 0..n {
+                let prev = if m.start == 0 {
+                    if count > 0 {
+                        continue;
+                    }
+                    0.0
+                } else {
+                    let Some((prev, last)) = &best[m.start][count] else {
+                        continue;
+                    };
+                    // Two brute-force runs in a row are one longer run
+                    if m.pattern == Pattern::BruteForce && last.pattern == Pattern::BruteForce {
+                        continue;
+                    }
+                    *prev
+                };
+                let total = prev + m.guesses_log10;
+                let slot = &mut best[end][count + 1];
+                if slot.as_ref().is_none_or(|(t, _)| total < *t) {
+                    *slot = Some((total, m.clone()));
+                }
+            }
+        }
+    }
+
+    let log10_factorial = |c: usize| (1..=c).map(|i| (i as f64).log10()).sum::<f64>();
+    let (count, total) = (1..=n)
+        .filter_map(|c| {
+            best[n][c]
+                .as_ref()
+                .map(|(t, _)| (c, t + log10_factorial(c)))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+
+    let mut sequence = Vec::new();
+    let (mut end, mut c) = (n, count);
+    while c > 0 {
+        let (_, m) = best[end][c].clone().unwrap();
+        end = m.start;
+        sequence.push(m);
+        c -= 1;
+    }
+    sequence.reverse();
+    (total, sequence)
+}
+
+fn find_matches(chars: &[char]) -> Vec<Match> {
+    let n = chars.len();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != n {
+        // Case mapping changed the length; analyse without dictionary lookups
+        return Vec::new();
+    }
+    let plain: Vec<char> = lower.iter().map(|&c| unleet(c)).collect();
+    let mut matches = Vec::new();
+
+    for start in 0..n {
+        for end in start + 3..=n {
+            let word: String = plain[start..end].iter().collect();
+            let reversed: String = plain[start..end].iter().rev().collect();
+            for (candidate, is_reversed) in [(&word, false), (&reversed, true)] {
+                let rank = COMMON
+                    .split_whitespace()
+                    .position(|c| c == candidate)
+                    .map(|i| (Pattern::Common, i + 1))
+                    .or_else(|| {
+                        Language::English
+                            .find_word(candidate)
+                            .map(|_| (Pattern::Word, Language::English.word_list().len()))
+                    });
+                let Some((pattern, rank)) = rank else {
+                    continue;
+                };
+                let uppercase = chars[start..end].iter().any(|c| c.is_uppercase());
+                let substituted = lower[start..end] != plain[start..end];
+                let mut guesses = (rank as f64).log10();
+                if uppercase {
+                    guesses += uppercase_variations(&chars[start..end]);
+                }
+                if substituted {
+                    guesses += 2f64.log10();
+                }
+                if is_reversed {
+                    guesses += 2f64.log10();
+                }
+                matches.push(Match {
+                    start,
+                    end,
+                    pattern,
+                    guesses_log10: guesses,
+                    uppercase,
+                    substituted,
+                });
+            }
+        }
+    }
+
+    let runs = |pattern: Pattern, step: &dyn Fn(char, char) -> bool, base: &dyn Fn(char) -> f64| {
+        let mut found = Vec::new();
+        let mut start = 0;
+        for i in 1..=n {
+            if i < n && step(lower[i - 1], lower[i]) {
+                continue;
+            }
+            let len = i - start;
+            if len >= 3 {
+                found.push(Match {
+                    start,
+                    end: i,
+                    pattern,
+                    guesses_log10: (base(lower[start]) * len as f64).log10(),
+                    uppercase: false,
+                    substituted: false,
+                });
+            }
+            start = i;
+        }
+        found
+    };
+    let delta = |a: char, b: char| b as i64 - a as i64;
+    matches.extend(runs(
+        Pattern::Sequence,
+        &|a, b| {
+            (delta(a, b) == 1 || delta(a, b) == -1)
+                && a.is_ascii_alphanumeric()
+                && b.is_ascii_alphanumeric()
+        },
+        &|first| match first {
+            'a' | 'z' | '0' | '1' | '9' => 4.0,
+            c if c.is_ascii_digit() => 10.0,
+            _ => 26.0,
+        },
+    ));
+    matches.extend(runs(Pattern::Repeat, &|a, b| a == b, &|first| {
+        cardinality(first)
+    }));
+    matches.extend(runs(Pattern::Keyboard, &adjacent_keys, &|_| 47.0));
+
+    for start in 0..n.saturating_sub(3) {
+        let digits: String = lower[start..start + 4].iter().collect();
+        if let Ok(year @ 1900..=2099) = digits.parse::<i32>() {
+            matches.push(Match {
+                start,
+                end: start + 4,
+                pattern: Pattern::Year,
+                guesses_log10: ((year - 2026).abs().max(20) as f64).log10(),
+                uppercase: false,
+                substituted: false,
+            });
+        }
+    }
+    matches
+}
+
+/// log10 of the capitalizations an attacker tries before this one.
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let first_only = upper == 1 && word[0].is_uppercase();
+    let last_only = upper == 1 && word[word.len() - 1].is_uppercase();
+    if upper == word.len() || first_only || last_only {
+        2f64.log10()
+    } else {
+        (upper as f64 * 2f64.log10()).max(2f64.log10())
+    }
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        c => c,
+    }
+}
+
+fn cardinality(c: char) -> f64 {
+    if c.is_ascii_digit() {
+        10.0
+    } else if c.is_ascii_lowercase() {
+        26.0
+    } else {
+        33.0
+    }
+}
+
+fn adjacent_keys(a: char, b: char) -> bool {
+    KEYBOARD_ROWS.iter().any(|row| {
+        let row: Vec<char> = row.chars().collect();
+        row.windows(2)
+            .any(|w| (w[0] == a && w[1] == b) || (w[0] == b && w[1] == a))
+    })
+}
+
+fn feedback(score: u8, sequence: &[Match], len: usize) -> (Option<String>, Vec<String>) {
+    if len == 0 {
+        return (
+            Some("Enter a password".to_string()),
+            vec!["Use a few words, avoid common phrases".to_string()],
+        );
+    }
+    if score >= 3 {
+        return (None, Vec::new());
+    }
+    let mut suggestions = vec!["Add another word or two; uncommon words are better".to_string()];
+    let longest = sequence.iter().max_by_key(|m| m.end - m.start);
+    let warning = longest.and_then(|m| match m.pattern {
+        Pattern::Common => Some("This is a very common password"),
+        Pattern::Word if sequence.len() == 1 => Some("A single word is easy to guess"),
+        Pattern::Word => None,
+        Pattern::Sequence => Some("Sequences like abc or 6543 are easy to guess"),
+        Pattern::Repeat => Some("Repeats like \"aaa\" are easy to guess"),
+        Pattern::Keyboard => Some("Straight rows of keys are easy to guess"),
+        Pattern::Year => Some("Years are easy to guess"),
+        Pattern::BruteForce if len < 10 => Some("Short passwords are easy to guess"),
+        Pattern::BruteForce => None,
+    });
+    if sequence.iter().any(|m| m.uppercase) {
+        suggestions.push("Capitalization doesn't help very much".to_string());
+    }
+    if sequence.iter().any(|m| m.substituted) {
+        suggestions.push(
+            "Predictable substitutions like '@' instead of 'a' don't help very much".to_string(),
+        );
+    }
+    if sequence.iter().any(|m| m.pattern == Pattern::Year) {
+        suggestions.push("Avoid years that are associated with you".to_string());
+    }
+    (warning.map(str::to_string), suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_passwords_score_low() {
+        for password in [
+            "",
+            "a",
+            "password",
+            "P@ssw0rd",
+            "qwerty123",
+            "abcdefgh",
+            "aaaaaaaaaa",
+            "1984",
+        ] {
+            let strength = estimate(password);
+            assert!(strength.score <= 1, "{password}: {strength:?}");
+            assert!(strength.warning.is_some(), "{password}: {strength:?}");
+        }
+        assert_eq!(
+            estimate("password").warning.as_deref(),
+            Some("This is a very common password")
+        );
+    }
+
+    #[test]
+    fn strong_passwords_score_high() {
+        for password in [
+            "correct horse battery staple",
+            "Tr0ub4dour&3xq!Vz9",
+            "v8#kQ2!mZp4&wL",
+        ] {
+            let strength = estimate(password);
+            assert!(strength.score >= 3, "{password}: {strength:?}");
+            assert!(strength.warning.is_none());
+        }
+        assert_eq!(
+            estimate("correct horse battery staple").crack_time(),
+            "centuries"
+        );
+    }
+
+    #[test]
+    fn crack_time_is_readable() {
+        let at = |guesses_log10| Strength {
+            score: 0,
+            guesses_log10,
+            warning: None,
+            suggestions: Vec::new(),
+        };
+        assert_eq!(at(2.0).crack_time(), "less than a second");
+        assert_eq!(at(4.0 + 3600f64.log10()).crack_time(), "1 hour");
+        assert_eq!(at(4.0 + (3.0 * 86400f64).log10()).crack_time(), "3 days");
+    }
+}
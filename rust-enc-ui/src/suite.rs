@@ -0,0 +1,122 @@
+use aes_gcm::Aes256Gcm;
+use aes_gcm_siv::Aes256GcmSiv;
+use anyhow::{Result, anyhow, bail};
+
+/// Bytes the AEAD adds to the payload.
+pub(crate) const TAG_LEN: usize = 16;
+
+/// AEAD used for the payload. Both take a 32-byte key and 12-byte nonce and add a
+/// 16-byte tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Suite {
+    #[default]
+    Aes256Gcm,
+    /// Nonce-misuse resistant: if a nonce ever repeats (e.g. an RNG restored from a VM
+    /// snapshot), it only reveals whether two plaintexts were equal.
+    Aes256GcmSiv,
+}
+
+impl Suite {
+    pub const ALL: [Suite; 2] = [Suite::Aes256Gcm, Suite::Aes256GcmSiv];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Suite::Aes256Gcm => "AES-256-GCM",
+            Suite::Aes256GcmSiv => "AES-256-GCM-SIV",
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Suite::Aes256Gcm => 1,
+            Suite::Aes256GcmSiv => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Suite::Aes256Gcm),
+            2 => Ok(Suite::Aes256GcmSiv),
+            _ => bail!("Unsupported cipher suite {id}"),
+        }
+    }
+
+    pub(crate) fn encrypt(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        msg: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>> {
+        use aes_gcm::aead::{Aead, KeyInit, Payload};
+        let payload = Payload { msg, aad };
+        match self {
+            Suite::Aes256Gcm => Aes256Gcm::new(key.into()).encrypt(nonce.into(), payload),
+            Suite::Aes256GcmSiv => Aes256GcmSiv::new(key.into()).encrypt(nonce.into(), payload),
+        }
+        .map_err(|_| anyhow!("Encryption failed"))
+    }
+
+    pub(crate) fn decrypt(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        msg: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>> {
+        use aes_gcm::aead::{Aead, KeyInit, Payload};
+        let payload = Payload { msg, aad };
+        match self {
+            Suite::Aes256Gcm => Aes256Gcm::new(key.into()).decrypt(nonce.into(), payload),
+            Suite::Aes256GcmSiv => Aes256GcmSiv::new(key.into()).decrypt(nonce.into(), payload),
+        }
+        .map_err(|_| anyhow!("Decryption failed (wrong password or corrupted file)"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suites_roundtrip_and_differ() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let gcm = Suite::Aes256Gcm
+            .encrypt(&key, &nonce, b"msg", b"aad")
+            .unwrap();
+        let siv = Suite::Aes256GcmSiv
+            .encrypt(&key, &nonce, b"msg", b"aad")
+            .unwrap();
+        assert_ne!(gcm, siv);
+        for (suite, ct) in [(Suite::Aes256Gcm, &gcm), (Suite::Aes256GcmSiv, &siv)] {
+            assert_eq!(Suite::from_id(suite.id()).unwrap(), suite);
+            assert_eq!(suite.decrypt(&key, &nonce, ct, b"aad").unwrap(), b"msg");
+            assert!(suite.decrypt(&key, &nonce, ct, b"other").is_err());
+        }
+    }
+
+    #[test]
+    fn siv_nonce_reuse_only_reveals_equality() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let a = Suite::Aes256GcmSiv
+            .encrypt(&key, &nonce, b"attack at dawn", b"")
+            .unwrap();
+        let b = Suite::Aes256GcmSiv
+            .encrypt(&key, &nonce, b"attack at dusk", b"")
+            .unwrap();
+        let a_again = Suite::Aes256GcmSiv
+            .encrypt(&key, &nonce, b"attack at dawn", b"")
+            .unwrap();
+        assert_eq!(a, a_again);
+        // With GCM the XOR of the ciphertexts would equal the XOR of the plaintexts
+        let xor_ct: Vec<u8> = a.iter().zip(&b).map(|(x, y)| x ^ y).collect();
+        let xor_pt: Vec<u8> = b"attack at dawn"
+            .iter()
+            .zip(b"attack at dusk")
+            .map(|(x, y)| x ^ y)
+            .collect();
+        assert_ne!(xor_ct[..xor_pt.len()], xor_pt[..]);
+    }
+}
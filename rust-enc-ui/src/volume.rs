@@ -0,0 +1,169 @@
+//! Splitting encrypted output into fixed-size volumes `NAME.001`, `NAME.002`, …
+//!
+//! Each volume is `"RVOL" | FORMAT (u8) | FILE_ID (16) | INDEX (u32 LE) | COUNT (u32 LE) |
+//! PART`, where `INDEX` counts from 1 and the parts concatenate to the encrypted file.
+//! The reassembled file is authenticated as usual, so the volume headers need no MAC.
+
+use crate::error::Error;
+use anyhow::{Context, Result, anyhow, bail};
+use getrandom::fill;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"RVOL";
+const FORMAT: u8 = 1;
+pub(crate) const HEADER_LEN: usize = 4 + 1 + 16 + 4 + 4;
+
+struct VolumeHeader {
+    file_id: [u8; 16],
+    index: u32,
+    count: u32,
+}
+
+impl VolumeHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC || data[4] != FORMAT {
+            return None;
+        }
+        Some(Self {
+            file_id: data[5..21].try_into().unwrap(),
+            index: u32::from_le_bytes(data[21..25].try_into().unwrap()),
+            count: u32::from_le_bytes(data[25..29].try_into().unwrap()),
+        })
+    }
+}
+
+pub(crate) fn is_volume(data: &[u8]) -> bool {
+    VolumeHeader::parse(data).is_some()
+}
+
+/// `NAME.001` for `NAME` and index 1.
+fn volume_path(base: &Path, index: u32) -> PathBuf {
+    let mut name = OsString::from(base.as_os_str());
+    name.push(format!(".{index:03}"));
+    PathBuf::from(name)
+}
+
+/// Writes `data` as volumes of at most `volume_size` bytes next to `base` and returns
+/// their paths.
+pub(crate) fn write(base: &Path, data: &[u8], volume_size: u64) -> Result<Vec<PathBuf>> {
+    let part_len = volume_size
+        .checked_sub(HEADER_LEN as u64)
+        .filter(|&n| n > 0)
+        .ok_or_else(|| anyhow!("Volume size must be more than {HEADER_LEN} bytes"))?;
+    let part_len = usize::try_from(part_len).unwrap_or(usize::MAX);
+    let count = u32::try_from(data.len().div_ceil(part_len).max(1))
+        .map_err(|_| anyhow!("Volume size too small for this file"))?;
+
+    let mut file_id = [0u8; 16];
+    fill(&mut file_id).map_err(|e| anyhow!("OS RNG failed for volume id: {e}"))?;
+
+    let mut paths = Vec::with_capacity(count as usize);
+    for index in 1..=count {
+        let start = (index as usize - 1) * part_len;
+        let part = &data[start..(start + part_len).min(data.len())];
+        let mut volume = Vec::with_capacity(HEADER_LEN + part.len());
+        volume.extend_from_slice(MAGIC);
+        volume.push(FORMAT);
+        volume.extend_from_slice(&file_id);
+        volume.extend_from_slice(&index.to_le_bytes());
+        volume.extend_from_slice(&count.to_le_bytes());
+        volume.extend_from_slice(part);
+
+        let path = volume_path(base, index);
+        fs::write(&path, volume).with_context(|| format!("Writing {}", path.display()))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Reads `path`; if it is one volume of a set, reads and joins all of them.
+///
+/// Any volume of the set may be given. Missing, foreign and misplaced volumes are
+/// reported by path.
+pub(crate) fn read(path: &Path) -> Result<Vec<u8>> {
+    let data = fs::read(path).with_context(|| format!("Reading {}", path.display()))?;
+    let Some(first) = VolumeHeader::parse(&data) else {
+        return Ok(data);
+    };
+    let numbered = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.len() >= 3 && e.bytes().all(|b| b.is_ascii_digit()));
+    if !numbered {
+        bail!(
+            "{} is a volume but not named NAME.001, NAME.002, …",
+            path.display()
+        );
+    }
+    let base = path.with_extension("");
+
+    let mut joined = Vec::new();
+    for index in 1..=first.count {
+        let volume_path = volume_path(&base, index);
+        if !volume_path.exists() {
+            return Err(Error::VolumeMissing {
+                index,
+                count: first.count,
+                path: volume_path,
+            }
+            .into());
+        }
+        let volume =
+            fs::read(&volume_path).with_context(|| format!("Reading {}", volume_path.display()))?;
+        let header = match VolumeHeader::parse(&volume) {
+            Some(h) if h.file_id == first.file_id && h.count == first.count => h,
+            _ => return Err(Error::VolumeMismatch { path: volume_path }.into()),
+        };
+        if header.index != index {
+            return Err(Error::VolumeOutOfOrder {
+                expected: index,
+                found: header.index,
+                path: volume_path,
+            }
+            .into());
+        }
+        joined.extend_from_slice(&volume[HEADER_LEN..]);
+    }
+    Ok(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_join() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("data.renc");
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let paths = write(&base, &data, HEADER_LEN as u64 + 300).unwrap();
+        assert_eq!(paths.len(), 4);
+        assert_eq!(paths[0], dir.path().join("data.renc.001"));
+        assert_eq!(
+            fs::metadata(&paths[3]).unwrap().len(),
+            HEADER_LEN as u64 + 100
+        );
+
+        // Any volume of the set finds the others
+        assert_eq!(read(&paths[2]).unwrap(), data);
+    }
+
+    #[test]
+    fn non_volume_is_read_as_is() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.renc");
+        fs::write(&path, b"RENC...").unwrap();
+        assert_eq!(read(&path).unwrap(), b"RENC...");
+    }
+
+    #[test]
+    fn volume_size_must_fit_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("data.renc");
+        assert!(write(&base, b"data", HEADER_LEN as u64).is_err());
+    }
+}
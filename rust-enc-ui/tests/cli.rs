@@ -0,0 +1,189 @@
+use rust_enc_ui::{hybrid, keyring::Keyring};
+use std::fs;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+fn renc(args: &[&str], master: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-enc-ui"))
+        .args(args)
+        .env("RENC_TEST_MASTER", master)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn recipients_are_named_from_the_keyring() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let keyring_path = dir.path().join("keyring");
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"for alice")?;
+
+    let alice = hybrid::generate_key()?;
+    let mut keyring = Keyring::create(&keyring_path, "master")?;
+    keyring.add_contact("alice", alice.public_key().into())?;
+    keyring.add_identity("me", alice.clone().into())?;
+    keyring.save()?;
+    let keyring_args = [
+        "--keyring",
+        keyring_path.to_str().unwrap(),
+        "--keyring-passphrase",
+        "env:RENC_TEST_MASTER",
+    ];
+    let paths = [input.to_str().unwrap(), encrypted.to_str().unwrap()];
+
+    let unknown = renc(
+        &[
+            &["encrypt", "--recipient", "bob"],
+            &paths[..],
+            &keyring_args,
+        ]
+        .concat(),
+        "master",
+    );
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("No contact named bob"));
+    assert!(!encrypted.exists());
+
+    let wrong = renc(
+        &[
+            &["encrypt", "--recipient", "alice"],
+            &paths[..],
+            &keyring_args,
+        ]
+        .concat(),
+        "not the master",
+    );
+    assert!(!wrong.status.success());
+
+    let fingerprint = hybrid::fingerprint(&alice.public_key());
+    for name in ["alice", fingerprint.as_str()] {
+        let encrypt = renc(
+            &[&["encrypt", "--recipient", name], &paths[..], &keyring_args].concat(),
+            "master",
+        );
+        assert!(encrypt.status.success(), "{encrypt:?}");
+        let decrypt = renc(
+            &[
+                &[
+                    "decrypt",
+                    encrypted.to_str().unwrap(),
+                    output.to_str().unwrap(),
+                ][..],
+                &keyring_args,
+            ]
+            .concat(),
+            "master",
+        );
+        assert!(decrypt.status.success(), "{decrypt:?}");
+        assert_eq!(fs::read(&output)?, b"for alice");
+        fs::remove_file(&encrypted)?;
+        fs::remove_file(&output)?;
+    }
+
+    // With the keyring open, a file for no one in it still opens with its password
+    let encrypt = renc(
+        &[
+            "encrypt",
+            paths[0],
+            paths[1],
+            "--password",
+            "env:RENC_TEST_MASTER",
+        ],
+        "master",
+    );
+    assert!(encrypt.status.success(), "{encrypt:?}");
+    let decrypt = renc(
+        &[
+            &[
+                "decrypt",
+                encrypted.to_str().unwrap(),
+                output.to_str().unwrap(),
+                "--password",
+                "env:RENC_TEST_MASTER",
+            ][..],
+            &keyring_args,
+        ]
+        .concat(),
+        "master",
+    );
+    assert!(decrypt.status.success(), "{decrypt:?}");
+    assert_eq!(fs::read(&output)?, b"for alice");
+    Ok(())
+}
+
+#[test]
+fn passwords_come_from_a_source() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    let password_file = dir.path().join("password");
+    fs::write(&input, b"nightly backup")?;
+    fs::write(&password_file, "hunter2\nsecond line is ignored\n")?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&password_file, fs::Permissions::from_mode(0o600))?;
+    }
+    let (input, encrypted, output) = (
+        input.to_str().unwrap(),
+        encrypted.to_str().unwrap(),
+        output.to_str().unwrap(),
+    );
+
+    let encrypt = renc(
+        &[
+            "encrypt",
+            input,
+            encrypted,
+            "--password",
+            "env:RENC_TEST_MASTER",
+        ],
+        "hunter2",
+    );
+    assert!(encrypt.status.success(), "{encrypt:?}");
+
+    let from_file = format!("file:{}", password_file.display());
+    let decrypt = renc(
+        &["decrypt", encrypted, output, "--password", &from_file],
+        "",
+    );
+    assert!(decrypt.status.success(), "{decrypt:?}");
+    assert_eq!(fs::read(output)?, b"nightly backup");
+    fs::remove_file(output)?;
+
+    // Errors name neither the password nor where it came from
+    let wrong = renc(
+        &[
+            "decrypt",
+            encrypted,
+            output,
+            "--password",
+            "env:RENC_TEST_MASTER",
+        ],
+        "hunter3",
+    );
+    assert!(!wrong.status.success());
+    let stderr = String::from_utf8_lossy(&wrong.stderr);
+    assert!(!stderr.contains("hunter3") && !stderr.contains("RENC_TEST_MASTER"));
+    assert!(!std::path::Path::new(output).exists());
+
+    let unset = renc(
+        &[
+            "decrypt",
+            encrypted,
+            output,
+            "--password",
+            "env:RENC_TEST_UNSET",
+        ],
+        "",
+    );
+    assert!(!unset.status.success());
+    assert!(!String::from_utf8_lossy(&unset.stderr).contains("RENC_TEST_UNSET"));
+
+    let nothing = renc(&["encrypt", input, encrypted], "");
+    assert!(!nothing.status.success());
+    Ok(())
+}
@@ -1,5 +1,12 @@
-use rust_enc_ui::{run_decrypt, run_encrypt};
+use rust_enc_ui::{
+    DecryptOptions, EncryptOptions, Error, KdfLimits, KdfParams, MAX_COMMENT_LEN, Padding,
+    ShareSplit, Suite, hybrid, keyring::Keyring, paper::PaperBackup, recipient,
+    run_change_password, run_check, run_decrypt, run_decrypt_with, run_encrypt, run_encrypt_with,
+    run_inspect, run_repair, signing,
+};
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tempfile::tempdir;
 
 #[test]
@@ -29,5 +36,1476 @@ fn decrypt_with_wrong_password_fails() -> anyhow::Result<()> {
     run_encrypt(Some(input.clone()), Some(encrypted.clone()), "correct")?;
     let result = run_decrypt(Some(encrypted), Some(dir.path().join("out.txt")), "wrong");
     assert!(result.is_err());
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<Error>(),
+        Some(Error::WrongPassword)
+    ));
+    Ok(())
+}
+
+#[test]
+fn decrypt_version1_file() -> anyhow::Result<()> {
+    use aes_gcm::{Aes256Gcm, KeyInit, aead::Aead};
+    use argon2::Argon2;
+
+    let dir = tempdir()?;
+    let encrypted = dir.path().join("v1.bin");
+    let output = dir.path().join("out.txt");
+
+    let salt = [3u8; 16];
+    let nonce = [4u8; 12];
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(b"pw", &salt, &mut key)
+        .unwrap();
+    let ciphertext = Aes256Gcm::new((&key).into())
+        .encrypt((&nonce).into(), b"legacy".as_ref())
+        .unwrap();
+
+    let mut data = b"RENC".to_vec();
+    data.push(1);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    fs::write(&encrypted, data)?;
+
+    run_decrypt(Some(encrypted), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(output)?, b"legacy");
+    Ok(())
+}
+
+#[test]
+fn unicode_passwords_are_normalized() -> anyhow::Result<()> {
+    use aes_gcm::{Aes256Gcm, KeyInit, aead::Aead};
+    use argon2::Argon2;
+
+    let composed = "caf\u{e9}";
+    let decomposed = "cafe\u{301}";
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"typed on a Mac")?;
+
+    run_encrypt(Some(input), Some(encrypted.clone()), decomposed)?;
+    run_decrypt(Some(encrypted), Some(output.clone()), composed)?;
+    assert_eq!(fs::read(&output)?, b"typed on a Mac");
+    fs::remove_file(&output)?;
+
+    // Version 1 files keep the bytes as typed
+    let salt = [3u8; 16];
+    let nonce = [4u8; 12];
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(decomposed.as_bytes(), &salt, &mut key)
+        .unwrap();
+    let ciphertext = Aes256Gcm::new((&key).into())
+        .encrypt((&nonce).into(), b"legacy".as_ref())
+        .unwrap();
+    let legacy = dir.path().join("v1.bin");
+    let mut data = b"RENC".to_vec();
+    data.push(1);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    fs::write(&legacy, data)?;
+
+    assert!(run_decrypt(Some(legacy.clone()), Some(output.clone()), composed).is_err());
+    run_decrypt(Some(legacy), Some(output.clone()), decomposed)?;
+    assert_eq!(fs::read(output)?, b"legacy");
+    Ok(())
+}
+
+#[test]
+fn oversized_kdf_params_rejected_before_derivation() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    fs::write(&input, b"data")?;
+    run_encrypt(Some(input), Some(encrypted.clone()), "pw")?;
+
+    // Ask for 64 GiB of Argon2 memory in the first key slot
+    let mut data = fs::read(&encrypted)?;
+    data[59..63].copy_from_slice(&(64 * 1024 * 1024u32).to_le_bytes());
+    fs::write(&encrypted, data)?;
+
+    let err = run_decrypt(Some(encrypted), Some(dir.path().join("out.txt")), "pw").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::KdfLimitExceeded {
+            param: "memory (KiB)",
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn kdf_limits_can_be_raised() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"data")?;
+
+    let kdf = KdfParams {
+        memory_kib: 64,
+        iterations: 32,
+        parallelism: 1,
+    };
+    run_encrypt_with(
+        Some(input),
+        Some(encrypted.clone()),
+        "pw",
+        &EncryptOptions {
+            kdf,
+            ..EncryptOptions::default()
+        },
+    )?;
+
+    let err = run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::KdfLimitExceeded {
+            param: "iterations",
+            ..
+        })
+    ));
+
+    let opts = DecryptOptions {
+        kdf_limits: KdfLimits {
+            max_iterations: 32,
+            ..KdfLimits::default()
+        },
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(Some(encrypted), Some(output.clone()), "pw", &opts)?;
+    assert_eq!(fs::read(output)?, b"data");
+    Ok(())
+}
+
+#[test]
+fn keyfile_only_roundtrip() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    let keyfile = dir.path().join("key.jpg");
+    fs::write(&input, b"data")?;
+    fs::write(&keyfile, b"any bytes at all")?;
+
+    let enc = EncryptOptions {
+        keyfile: Some(keyfile.clone()),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "", &enc)?;
+
+    let dec = DecryptOptions {
+        keyfile: Some(keyfile),
+        ..DecryptOptions::default()
+    };
+    // The password is not a factor, so whatever is typed is ignored
+    run_decrypt_with(Some(encrypted), Some(output.clone()), "ignored", &dec)?;
+    assert_eq!(fs::read(output)?, b"data");
+    Ok(())
+}
+
+#[test]
+fn password_and_keyfile_both_required() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    let keyfile = dir.path().join("key.bin");
+    let other_keyfile = dir.path().join("other.bin");
+    fs::write(&input, b"data")?;
+    fs::write(&keyfile, b"key one")?;
+    fs::write(&other_keyfile, b"key two")?;
+
+    let enc = EncryptOptions {
+        keyfile: Some(keyfile.clone()),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &enc)?;
+
+    let err = run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::KeyfileRequired)
+    ));
+
+    let with = |path| DecryptOptions {
+        keyfile: Some(path),
+        ..DecryptOptions::default()
+    };
+    let wrong_keyfile = with(other_keyfile);
+    assert!(
+        run_decrypt_with(
+            Some(encrypted.clone()),
+            Some(output.clone()),
+            "pw",
+            &wrong_keyfile
+        )
+        .is_err()
+    );
+    let right_keyfile = with(keyfile);
+    assert!(
+        run_decrypt_with(
+            Some(encrypted.clone()),
+            Some(output.clone()),
+            "pX",
+            &right_keyfile
+        )
+        .is_err()
+    );
+    run_decrypt_with(Some(encrypted), Some(output.clone()), "pw", &right_keyfile)?;
+    assert_eq!(fs::read(output)?, b"data");
+    Ok(())
+}
+
+#[test]
+fn change_password_rewrites_only_the_header() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"payload stays put")?;
+    run_encrypt(Some(input), Some(encrypted.clone()), "old")?;
+    let before = fs::read(&encrypted)?;
+
+    run_change_password(
+        Some(encrypted.clone()),
+        "old",
+        "new",
+        &DecryptOptions::default(),
+        &EncryptOptions::default(),
+    )?;
+    let after = fs::read(&encrypted)?;
+    assert_eq!(before.len(), after.len());
+    let ciphertext_len = b"payload stays put".len() + 16;
+    assert_eq!(
+        before[before.len() - ciphertext_len..],
+        after[after.len() - ciphertext_len..]
+    );
+
+    assert!(run_decrypt(Some(encrypted.clone()), Some(output.clone()), "old").is_err());
+    run_decrypt(Some(encrypted), Some(output.clone()), "new")?;
+    assert_eq!(fs::read(output)?, b"payload stays put");
+    Ok(())
+}
+
+/// Offset of the key commitment in a version 9 header: magic, version, suite, flags, nonce.
+const COMMITMENT: std::ops::Range<usize> = 20..52;
+/// Offset of the slot count, after an empty extension area.
+const SLOTS: usize = COMMITMENT.end + 2;
+/// Encoded length of a password slot: kind, length, params, key check, wrapped key.
+const SLOT_LEN: usize = 1 + 2 + 29 + 16 + 60;
+
+#[test]
+fn tampered_key_commitment_is_detected() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    fs::write(&input, b"data")?;
+    run_encrypt(Some(input), Some(encrypted.clone()), "pw")?;
+
+    let mut data = fs::read(&encrypted)?;
+    data[COMMITMENT.start] ^= 1;
+    fs::write(&encrypted, data)?;
+
+    let err = run_decrypt(Some(encrypted), Some(dir.path().join("out.txt")), "pw").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::KeyCommitmentMismatch)
+    ));
+    Ok(())
+}
+
+#[test]
+fn slot_from_another_file_cannot_unlock_payload() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let a = dir.path().join("a.bin");
+    let b = dir.path().join("b.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"data")?;
+    run_encrypt(Some(input.clone()), Some(a.clone()), "pw")?;
+    run_encrypt(Some(input), Some(b.clone()), "pw")?;
+
+    // Graft file A's key slot onto file B: it opens under the same password but yields
+    // A's data key, which B's commitment does not match.
+    let a_data = fs::read(&a)?;
+    let mut b_data = fs::read(&b)?;
+    let slots = SLOTS..SLOTS + 1 + SLOT_LEN;
+    b_data[slots.clone()].copy_from_slice(&a_data[slots]);
+    fs::write(&b, b_data)?;
+
+    let err = run_decrypt(Some(b), Some(output.clone()), "pw").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::KeyCommitmentMismatch)
+    ));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn gcm_siv_suite_roundtrip() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"snapshot-safe")?;
+
+    let opts = EncryptOptions {
+        suite: Suite::Aes256GcmSiv,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    assert_eq!(fs::read(&encrypted)?[5], 2, "suite id recorded in header");
+
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(&output)?, b"snapshot-safe");
+
+    // The suite byte is authenticated
+    let mut data = fs::read(&encrypted)?;
+    data[5] = 1;
+    fs::write(&encrypted, data)?;
+    assert!(run_decrypt(Some(encrypted), Some(output), "pw").is_err());
+    Ok(())
+}
+
+#[test]
+fn damaged_wrapped_key_is_not_reported_as_wrong_password() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    fs::write(&input, b"data")?;
+    run_encrypt(Some(input), Some(encrypted.clone()), "pw")?;
+
+    let mut data = fs::read(&encrypted)?;
+    data[SLOTS + SLOT_LEN] ^= 1; // last byte of the wrapped key's tag
+    fs::write(&encrypted, data)?;
+
+    let out = dir.path().join("out.txt");
+    let err = run_decrypt(Some(encrypted.clone()), Some(out.clone()), "pw").unwrap_err();
+    assert!(err.to_string().contains("Key slot is corrupted"));
+    let err = run_decrypt(Some(encrypted), Some(out), "typo").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::WrongPassword)
+    ));
+    Ok(())
+}
+
+#[test]
+fn padding_hides_length_and_is_stripped() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let opts = EncryptOptions {
+        padding: Padding::Bucket(4096),
+        ..EncryptOptions::default()
+    };
+
+    let mut sizes = Vec::new();
+    for (name, data) in [("short", &b"hi"[..]), ("long", &[7u8; 3000][..])] {
+        let input = dir.path().join(name);
+        let encrypted = dir.path().join(format!("{name}.renc"));
+        let output = dir.path().join(format!("{name}.out"));
+        fs::write(&input, data)?;
+        run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+        sizes.push(fs::metadata(&encrypted)?.len());
+
+        run_decrypt(Some(encrypted), Some(output.clone()), "pw")?;
+        assert_eq!(fs::read(output)?, data);
+    }
+    assert_eq!(sizes[0], sizes[1]);
+
+    // A huge bucket is refused up front instead of aborting on allocation
+    let input = dir.path().join("short");
+    let huge = EncryptOptions {
+        padding: Padding::Bucket(u64::MAX),
+        ..EncryptOptions::default()
+    };
+    let encrypted = dir.path().join("huge.renc");
+    assert!(run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &huge).is_err());
+    assert!(!encrypted.exists());
+    Ok(())
+}
+
+#[test]
+fn padme_roundtrip() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, vec![1u8; 1000])?;
+
+    let opts = EncryptOptions {
+        padding: Padding::Padme,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    run_decrypt(Some(encrypted), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(output)?, vec![1u8; 1000]);
+    Ok(())
+}
+
+#[test]
+fn signed_file_reports_signer() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"from alice")?;
+
+    let alice = signing::generate_signing_key()?;
+    let opts = EncryptOptions {
+        signing_key: Some(alice.clone()),
+        padding: Padding::Padme,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+
+    let report = run_decrypt_with(
+        Some(encrypted.clone()),
+        Some(output.clone()),
+        "pw",
+        &DecryptOptions::default(),
+    )?;
+    assert_eq!(fs::read(&output)?, b"from alice");
+    let signer = report.signer.expect("file should be signed");
+    assert_eq!(signer.public_key, alice.verifying_key());
+    assert_eq!(
+        signer.fingerprint,
+        signing::fingerprint(&alice.verifying_key())
+    );
+    assert!(!signer.trusted);
+
+    let trusting_alice = DecryptOptions {
+        trusted_signers: vec![alice.verifying_key()],
+        ..DecryptOptions::default()
+    };
+    let report = run_decrypt_with(Some(encrypted), Some(output), "pw", &trusting_alice)?;
+    assert!(report.signer.unwrap().trusted);
+    Ok(())
+}
+
+#[test]
+fn trusted_signers_reject_others_and_unsigned_files() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let signed = dir.path().join("signed.bin");
+    let unsigned = dir.path().join("unsigned.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"data")?;
+
+    let mallory = signing::generate_signing_key()?;
+    let opts = EncryptOptions {
+        signing_key: Some(mallory),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input.clone()), Some(signed.clone()), "pw", &opts)?;
+    run_encrypt(Some(input), Some(unsigned.clone()), "pw")?;
+
+    let trusting_alice = DecryptOptions {
+        trusted_signers: vec![signing::generate_signing_key()?.verifying_key()],
+        ..DecryptOptions::default()
+    };
+    let err =
+        run_decrypt_with(Some(signed), Some(output.clone()), "pw", &trusting_alice).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::UntrustedSigner { .. })
+    ));
+    let err =
+        run_decrypt_with(Some(unsigned), Some(output.clone()), "pw", &trusting_alice).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::SignatureRequired)
+    ));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn checksum_locates_corruption_without_password() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, vec![3u8; 20_000])?;
+
+    let opts = EncryptOptions {
+        checksum: true,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    let report = run_check(Some(encrypted.clone()))?;
+    assert!(report.is_intact());
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(&output)?, vec![3u8; 20_000]);
+
+    // Password changes rewrite the header, so the trailer must follow
+    run_change_password(
+        Some(encrypted.clone()),
+        "pw",
+        "new",
+        &DecryptOptions::default(),
+        &EncryptOptions::default(),
+    )?;
+    assert!(run_check(Some(encrypted.clone()))?.is_intact());
+
+    let mut data = fs::read(&encrypted)?;
+    data[10_000] ^= 0x40;
+    fs::write(&encrypted, data)?;
+    let report = run_check(Some(encrypted.clone()))?;
+    assert_eq!(report.damaged, vec![8192..12_288]);
+
+    let err = run_decrypt(Some(encrypted), Some(output), "new").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::Corrupted {
+            start: 8192,
+            end: 12_288
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn damaged_trailer_does_not_block_decryption() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    let plain: Vec<u8> = (0..20_000u32).map(|i| (i % 253) as u8).collect();
+    fs::write(&input, &plain)?;
+
+    let opts = EncryptOptions {
+        checksum: true,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    let clean = fs::read(&encrypted)?;
+
+    // Chunk count, chunk length, trailer hash and a chunk hash
+    for back in [1, 5, 20, 45, 100] {
+        let mut data = clean.clone();
+        let at = data.len() - back;
+        data[at] ^= 1;
+        fs::write(&encrypted, &data)?;
+        assert!(run_check(Some(encrypted.clone())).is_err());
+        let report = run_decrypt_with(
+            Some(encrypted.clone()),
+            Some(output.clone()),
+            "pw",
+            &DecryptOptions::default(),
+        )?;
+        assert!(report.integrity_skipped, "flip {back} bytes from the end");
+        assert_eq!(fs::read(&output)?, plain);
+    }
+
+    // The ciphertext is still authenticated without the trailer
+    let mut data = clean;
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    data[10_000] ^= 1;
+    fs::write(&encrypted, &data)?;
+    fs::remove_file(&output)?;
+    assert!(run_decrypt(Some(encrypted), Some(output.clone()), "pw").is_err());
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn check_without_trailer_is_refused() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    fs::write(&input, b"data")?;
+    run_encrypt(Some(input), Some(encrypted.clone()), "pw")?;
+
+    let err = run_check(Some(encrypted)).unwrap_err();
+    assert!(err.to_string().contains("no checksum trailer"));
+    Ok(())
+}
+
+#[test]
+fn parity_repairs_damage_before_decryption() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    let plain: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(&input, &plain)?;
+
+    let opts = EncryptOptions {
+        parity: 2,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    let clean = fs::read(&encrypted)?;
+
+    // Two flipped bytes in different chunks of the header and ciphertext
+    let mut data = clean.clone();
+    data[7] ^= 0xff;
+    data[30_000] ^= 0x01;
+    fs::write(&encrypted, &data)?;
+    assert_eq!(run_check(Some(encrypted.clone()))?.damaged.len(), 2);
+
+    let report = run_decrypt_with(
+        Some(encrypted.clone()),
+        Some(output.clone()),
+        "pw",
+        &DecryptOptions::default(),
+    )?;
+    assert_eq!(fs::read(&output)?, plain);
+    let repair = report.repair.expect("file has parity");
+    assert_eq!(repair.damaged, vec![0..4096, 28_672..32_768]);
+    assert!(repair.is_complete());
+
+    // Decryption repairs in memory only; run_repair fixes the file itself
+    assert_eq!(fs::read(&encrypted)?, data);
+    let repair = run_repair(Some(encrypted.clone()))?;
+    assert_eq!(repair.repaired_bytes(), 2 * 4096);
+    assert_eq!(fs::read(&encrypted)?, clean);
+    Ok(())
+}
+
+#[test]
+fn parity_reports_unrepairable_damage() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, vec![9u8; 20_000])?;
+
+    let opts = EncryptOptions {
+        parity: 1,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    let mut data = fs::read(&encrypted)?;
+    data[5_000] ^= 1;
+    data[10_000] ^= 1;
+    fs::write(&encrypted, data)?;
+
+    let report = run_repair(Some(encrypted.clone()))?;
+    assert_eq!(report.damaged_bytes(), 2 * 4096);
+    assert_eq!(report.repaired_bytes(), 0);
+    let err = run_decrypt(Some(encrypted), Some(output), "pw").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::Corrupted { start: 4096, .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn damaged_parity_metadata_does_not_block_decryption() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    let plain: Vec<u8> = (0..20_000u32).map(|i| (i % 249) as u8).collect();
+    fs::write(&input, &plain)?;
+
+    let opts = EncryptOptions {
+        parity: 2,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    let clean = fs::read(&encrypted)?;
+
+    // Parity footer, section hash and shard hashes: nothing to repair with, but the
+    // payload itself is intact
+    for back in [1, 20, 45, 100] {
+        let mut data = clean.clone();
+        let at = data.len() - back;
+        data[at] ^= 1;
+        fs::write(&encrypted, &data)?;
+        let report = run_decrypt_with(
+            Some(encrypted.clone()),
+            Some(output.clone()),
+            "pw",
+            &DecryptOptions::default(),
+        )?;
+        assert!(report.integrity_skipped, "flip {back} bytes from the end");
+        assert!(report.repair.is_none());
+        assert_eq!(fs::read(&output)?, plain);
+    }
+
+    // The checksum trailer sits just before the parity section, which now rebuilds it
+    let trailer_end = clean.len() - (2 * (4096 + 32) + 6 * 32 + 50);
+    for back in [1, 20, 45] {
+        let mut data = clean.clone();
+        data[trailer_end - back] ^= 1;
+        fs::write(&encrypted, &data)?;
+        assert!(run_check(Some(encrypted.clone())).is_err());
+        let report = run_decrypt_with(
+            Some(encrypted.clone()),
+            Some(output.clone()),
+            "pw",
+            &DecryptOptions::default(),
+        )?;
+        assert!(!report.integrity_skipped);
+        assert!(report.repair.expect("file has parity").is_complete());
+        assert_eq!(fs::read(&output)?, plain);
+        assert_eq!(run_repair(Some(encrypted.clone()))?.damaged.len(), 1);
+        assert_eq!(fs::read(&encrypted)?, clean);
+    }
+    Ok(())
+}
+
+#[test]
+fn volumes_reassemble_and_report_problems() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let base = dir.path().join("archive.renc");
+    let output = dir.path().join("out.txt");
+    let plain = vec![5u8; 2500];
+    fs::write(&input, &plain)?;
+
+    let opts = EncryptOptions {
+        volume_size: Some(1024),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(base.clone()), "pw", &opts)?;
+    assert!(!base.exists());
+    let volume = |n: u32| dir.path().join(format!("archive.renc.{n:03}"));
+    assert!(volume(3).exists() && !volume(4).exists());
+    assert!(fs::metadata(volume(1))?.len() <= 1024);
+
+    // Any volume of the set works as input
+    run_decrypt(Some(volume(2)), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(&output)?, plain);
+
+    // Swapped volumes
+    fs::rename(volume(2), dir.path().join("tmp"))?;
+    fs::rename(volume(3), volume(2))?;
+    fs::rename(dir.path().join("tmp"), volume(3))?;
+    let err = run_decrypt(Some(volume(1)), Some(output), "pw").unwrap_err();
+    match err.downcast_ref::<Error>() {
+        Some(Error::VolumeOutOfOrder {
+            expected: 2,
+            found: 3,
+            path,
+        }) => assert_eq!(path, &volume(2)),
+        other => panic!("unexpected error {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn missing_volume_is_named() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let base = dir.path().join("archive.renc");
+    fs::write(&input, vec![5u8; 2500])?;
+
+    let opts = EncryptOptions {
+        volume_size: Some(1024),
+        checksum: true,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(base), "pw", &opts)?;
+    let missing = dir.path().join("archive.renc.002");
+    fs::remove_file(&missing)?;
+
+    let err = run_check(Some(dir.path().join("archive.renc.001"))).unwrap_err();
+    match err.downcast_ref::<Error>() {
+        Some(Error::VolumeMissing {
+            index: 2,
+            count: 3,
+            path,
+        }) => assert_eq!(path, &missing),
+        other => panic!("unexpected error {other:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn detached_header_roundtrip() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let payload = dir.path().join("payload.bin");
+    let header = dir.path().join("payload.rhdr");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"public payload, private header")?;
+
+    let opts = EncryptOptions {
+        detached_header: Some(header.clone()),
+        checksum: true,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input.clone()), Some(payload.clone()), "pw", &opts)?;
+    assert!(!fs::read(&payload)?.starts_with(b"RENC"));
+    assert!(fs::read(&header)?.starts_with(b"RENC"));
+    assert!(run_check(Some(payload.clone()))?.is_intact());
+
+    // Without its header the payload is just noise
+    let err = run_decrypt(Some(payload.clone()), Some(output.clone()), "pw").unwrap_err();
+    assert!(err.to_string().contains(".rhdr"));
+
+    let with_header = DecryptOptions {
+        detached_header: Some(header.clone()),
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(
+        Some(payload.clone()),
+        Some(output.clone()),
+        "pw",
+        &with_header,
+    )?;
+    assert_eq!(fs::read(&output)?, b"public payload, private header");
+
+    // The password can be changed on the header file alone
+    run_change_password(
+        Some(header.clone()),
+        "pw",
+        "new",
+        &DecryptOptions::default(),
+        &EncryptOptions::default(),
+    )?;
+    run_decrypt_with(Some(payload.clone()), Some(output), "new", &with_header)?;
+
+    // A header from another encryption does not authenticate this payload
+    let other = EncryptOptions {
+        detached_header: Some(header),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(
+        Some(input),
+        Some(dir.path().join("other.bin")),
+        "new",
+        &other,
+    )?;
+    assert!(
+        run_decrypt_with(
+            Some(payload),
+            Some(dir.path().join("out2.txt")),
+            "new",
+            &with_header
+        )
+        .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn extension_area_is_authenticated() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    fs::write(&input, b"data")?;
+    run_encrypt(Some(input), Some(encrypted.clone()), "pw")?;
+
+    // Insert a non-critical extension the reader does not know: it parses, but the
+    // header no longer matches the AAD the payload was sealed with
+    let mut data = fs::read(&encrypted)?;
+    data[COMMITMENT.end..SLOTS].copy_from_slice(&4u16.to_le_bytes());
+    data.splice(SLOTS..SLOTS, [0x23, 0x01, 0, 0]);
+    fs::write(&encrypted, &data)?;
+
+    let err = run_decrypt(Some(encrypted), Some(dir.path().join("out.txt")), "pw").unwrap_err();
+    assert!(err.to_string().contains("Decryption failed"));
+    Ok(())
+}
+
+const COMMENT: &str = "Q3 payroll export, owner: finance";
+
+/// Encrypts `b"data"` to `cipher.bin` with `COMMENT` and returns the file's bytes.
+fn encrypt_with_comment(dir: &Path) -> anyhow::Result<Vec<u8>> {
+    let input = dir.join("plain.txt");
+    let encrypted = dir.join("cipher.bin");
+    fs::write(&input, b"data")?;
+    let opts = EncryptOptions {
+        comment: Some(COMMENT.to_string()),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    Ok(fs::read(encrypted)?)
+}
+
+#[test]
+fn comment_is_readable_and_verified() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    encrypt_with_comment(dir.path())?;
+    let encrypted = dir.path().join("cipher.bin");
+
+    let info = run_inspect(Some(encrypted.clone()))?;
+    assert_eq!(info.comment.as_deref(), Some(COMMENT));
+    assert_eq!(info.key_slots, 1);
+
+    let report = run_decrypt_with(
+        Some(encrypted),
+        Some(dir.path().join("out.txt")),
+        "pw",
+        &DecryptOptions::default(),
+    )?;
+    assert_eq!(report.comment.as_deref(), Some(COMMENT));
+    Ok(())
+}
+
+#[test]
+fn tampered_comment_is_detected() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let clean = encrypt_with_comment(dir.path())?;
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    // The extension area starts at SLOTS with TYPE | LENGTH | VALUE
+    let value = SLOTS + 4..SLOTS + 4 + COMMENT.len();
+    assert_eq!(&clean[value.clone()], COMMENT.as_bytes());
+
+    let mut edited = clean.clone();
+    edited[value.start..value.start + 2].copy_from_slice(b"Q4");
+    let mut shortened = clean.clone();
+    shortened[COMMITMENT.end..SLOTS].copy_from_slice(&(4 + 5u16).to_le_bytes());
+    shortened[SLOTS + 2..SLOTS + 4].copy_from_slice(&5u16.to_le_bytes());
+    shortened.drain(value.start + 5..value.end);
+    let mut removed = clean.clone();
+    removed[COMMITMENT.end..SLOTS].copy_from_slice(&0u16.to_le_bytes());
+    removed.drain(SLOTS..value.end);
+
+    for (name, data) in [
+        ("edited", edited),
+        ("shortened", shortened),
+        ("removed", removed),
+    ] {
+        fs::write(&encrypted, &data)?;
+        // The altered header still parses, but the file no longer decrypts
+        run_inspect(Some(encrypted.clone()))?;
+        let err = run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw").unwrap_err();
+        assert!(
+            err.to_string().contains("Decryption failed"),
+            "{name}: {err}"
+        );
+        assert!(!output.exists(), "{name}");
+    }
+    Ok(())
+}
+
+#[test]
+fn overlong_comment_is_refused() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    fs::write(&input, b"data")?;
+    let opts = EncryptOptions {
+        comment: Some("x".repeat(MAX_COMMENT_LEN + 1)),
+        ..EncryptOptions::default()
+    };
+    let err =
+        run_encrypt_with(Some(input), Some(dir.path().join("c.bin")), "pw", &opts).unwrap_err();
+    assert!(err.to_string().contains("Comment is longer"));
+    Ok(())
+}
+
+#[test]
+fn expired_file_is_refused_unless_allowed() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"retained data")?;
+
+    let expired = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let opts = EncryptOptions {
+        expires: Some(expired),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    assert_eq!(run_inspect(Some(encrypted.clone()))?.expires, Some(expired));
+
+    let err = run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw").unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Expired { at }) if *at == expired));
+    assert!(err.to_string().contains("2023-11-14 22:13:20 UTC"));
+    assert!(!output.exists());
+
+    let allow = DecryptOptions {
+        allow_expired: true,
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(Some(encrypted.clone()), Some(output.clone()), "pw", &allow)?;
+    assert_eq!(fs::read(&output)?, b"retained data");
+    fs::remove_file(&output)?;
+
+    // Pushing the date back is caught by authentication
+    let mut data = fs::read(&encrypted)?;
+    let value = SLOTS + 4..SLOTS + 12;
+    data[value].copy_from_slice(&4_102_444_799u64.to_le_bytes());
+    fs::write(&encrypted, data)?;
+    let err = run_decrypt(Some(encrypted), Some(output.clone()), "pw").unwrap_err();
+    assert!(err.to_string().contains("Decryption failed"), "{err}");
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn unexpired_file_decrypts() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    fs::write(&input, b"data")?;
+    let opts = EncryptOptions {
+        expires: Some(SystemTime::now() + Duration::from_secs(3600)),
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    run_decrypt(Some(encrypted), Some(dir.path().join("out.txt")), "pw")?;
+    Ok(())
+}
+
+/// Encrypts with a 3-of-5 share split and no password; returns the encrypted path and
+/// the shares.
+fn encrypt_with_shares(dir: &Path) -> anyhow::Result<(PathBuf, Vec<String>)> {
+    let input = dir.join("plain.txt");
+    let encrypted = dir.join("cipher.bin");
+    fs::write(&input, b"split secret")?;
+    let opts = EncryptOptions {
+        shares: Some(ShareSplit {
+            threshold: 3,
+            count: 5,
+        }),
+        ..EncryptOptions::default()
+    };
+    let report = run_encrypt_with(Some(input), Some(encrypted.clone()), "", &opts)?;
+    let shares = report.shares.iter().map(|s| s.to_string()).collect();
+    Ok((encrypted, shares))
+}
+
+fn decrypt_with_shares(encrypted: &Path, output: &Path, shares: &[&String]) -> anyhow::Result<()> {
+    let opts = DecryptOptions {
+        shares: shares.iter().map(|s| s.to_string()).collect(),
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(
+        Some(encrypted.to_path_buf()),
+        Some(output.to_path_buf()),
+        "",
+        &opts,
+    )
+    .map(|_| ())
+}
+
+#[test]
+fn any_threshold_of_shares_decrypts() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let (encrypted, shares) = encrypt_with_shares(dir.path())?;
+    assert_eq!(shares.len(), 5);
+    let output = dir.path().join("out.txt");
+
+    for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+        let chosen: Vec<&String> = subset.iter().map(|&i| &shares[i]).collect();
+        decrypt_with_shares(&encrypted, &output, &chosen)?;
+        assert_eq!(fs::read(&output)?, b"split secret");
+        fs::remove_file(&output)?;
+    }
+
+    // There is no password slot to try
+    assert!(run_decrypt(Some(encrypted), Some(output.clone()), "").is_err());
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn too_few_or_duplicate_shares_are_reported() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let (encrypted, shares) = encrypt_with_shares(dir.path())?;
+    let output = dir.path().join("out.txt");
+
+    let err = decrypt_with_shares(&encrypted, &output, &[&shares[0], &shares[3]]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::NotEnoughShares { have: 2, need: 3 })
+    ));
+
+    let err = decrypt_with_shares(&encrypted, &output, &[&shares[0], &shares[3], &shares[3]])
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::DuplicateShare { index: 4 })
+    ));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn foreign_or_mistyped_share_is_named() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let (encrypted, shares) = encrypt_with_shares(dir.path())?;
+    let other = tempdir()?;
+    let (_, foreign) = encrypt_with_shares(other.path())?;
+    let output = dir.path().join("out.txt");
+
+    let err = decrypt_with_shares(&encrypted, &output, &[&shares[0], &foreign[1], &shares[2]])
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::InvalidShare { index: 2 })
+    ));
+
+    let mut typo = shares[1].clone();
+    let last = typo.pop().unwrap();
+    typo.push(if last == 'a' { 'b' } else { 'a' });
+    let err =
+        decrypt_with_shares(&encrypted, &output, &[&shares[0], &typo, &shares[2]]).unwrap_err();
+    assert!(err.to_string().contains("Share 2 is malformed"), "{err}");
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn shares_and_password_coexist() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"either way")?;
+    let opts = EncryptOptions {
+        shares: Some(ShareSplit {
+            threshold: 2,
+            count: 3,
+        }),
+        ..EncryptOptions::default()
+    };
+    let report = run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?;
+    assert_eq!(run_inspect(Some(encrypted.clone()))?.key_slots, 2);
+
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(&output)?, b"either way");
+    fs::remove_file(&output)?;
+
+    // Shares can reset a forgotten password and keep working afterwards
+    let shares: Vec<String> = report.shares.iter().map(|s| s.to_string()).collect();
+    let unlock = DecryptOptions {
+        shares: shares[1..].to_vec(),
+        ..DecryptOptions::default()
+    };
+    run_change_password(
+        Some(encrypted.clone()),
+        "",
+        "new",
+        &unlock,
+        &EncryptOptions::default(),
+    )?;
+    assert_eq!(run_inspect(Some(encrypted.clone()))?.key_slots, 2);
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "new")?;
+    fs::remove_file(&output)?;
+    decrypt_with_shares(&encrypted, &output, &[&shares[0], &shares[2]])?;
+    assert_eq!(fs::read(&output)?, b"either way");
+    Ok(())
+}
+
+#[test]
+fn recovery_words_unlock_and_reset_password() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"recoverable")?;
+    let opts = EncryptOptions {
+        recovery_key: true,
+        ..EncryptOptions::default()
+    };
+    let report = run_encrypt_with(Some(input), Some(encrypted.clone()), "forgotten", &opts)?;
+    let words = report.recovery_words.unwrap().to_string();
+    assert_eq!(words.split(' ').count(), 24);
+
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "forgotten")?;
+    fs::remove_file(&output)?;
+    let unlock = DecryptOptions {
+        recovery_words: Some(words.to_uppercase()),
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(Some(encrypted.clone()), Some(output.clone()), "", &unlock)?;
+    assert_eq!(fs::read(&output)?, b"recoverable");
+    fs::remove_file(&output)?;
+
+    run_change_password(
+        Some(encrypted.clone()),
+        "",
+        "remembered",
+        &unlock,
+        &EncryptOptions::default(),
+    )?;
+    assert!(run_decrypt(Some(encrypted.clone()), Some(output.clone()), "forgotten").is_err());
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "remembered")?;
+    fs::remove_file(&output)?;
+    run_decrypt_with(Some(encrypted), Some(output.clone()), "", &unlock)?;
+    assert_eq!(fs::read(&output)?, b"recoverable");
+    Ok(())
+}
+
+#[test]
+fn wrong_or_mistyped_recovery_words_are_reported() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let other = dir.path().join("other.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"data")?;
+    let opts = EncryptOptions {
+        recovery_key: true,
+        ..EncryptOptions::default()
+    };
+    let words = run_encrypt_with(Some(input.clone()), Some(encrypted.clone()), "pw", &opts)?
+        .recovery_words
+        .unwrap()
+        .to_string();
+    let other_words = run_encrypt_with(Some(input), Some(other), "pw", &opts)?
+        .recovery_words
+        .unwrap()
+        .to_string();
+
+    let with_words = |words: String| DecryptOptions {
+        recovery_words: Some(words),
+        ..DecryptOptions::default()
+    };
+    let err = run_decrypt_with(
+        Some(encrypted.clone()),
+        Some(output.clone()),
+        "",
+        &with_words(other_words),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::WrongRecoveryKey)
+    ));
+
+    let mut typo: Vec<&str> = words.split(' ').collect();
+    typo[6] = "zzzz";
+    let err = run_decrypt_with(
+        Some(encrypted),
+        Some(output.clone()),
+        "",
+        &with_words(typo.join(" ")),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("Word 7"), "{err}");
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn paper_backup_restores_file_and_recovery_key() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"on paper")?;
+    let opts = EncryptOptions {
+        recovery_key: true,
+        ..EncryptOptions::default()
+    };
+    let words = run_encrypt_with(Some(input), Some(encrypted.clone()), "pw", &opts)?
+        .recovery_words
+        .unwrap();
+
+    // The whole small file, typed back from the page's lines
+    let page = dir.path().join("backup.html");
+    let backup = PaperBackup::File(fs::read(&encrypted)?);
+    backup.write_page(&page)?;
+    let html = fs::read_to_string(&page)?;
+    assert!(html.contains("<svg"));
+    assert!(html.contains(backup.to_lines()?.lines().next().unwrap()));
+    let PaperBackup::File(restored) = PaperBackup::import(&backup.to_lines()?)? else {
+        panic!("expected a file");
+    };
+    let copy = dir.path().join("copy.bin");
+    fs::write(&copy, restored)?;
+    run_decrypt(Some(copy.clone()), Some(output.clone()), "pw")?;
+    assert_eq!(fs::read(&output)?, b"on paper");
+    fs::remove_file(&output)?;
+
+    // The recovery key, from the QR text
+    let qr = PaperBackup::RecoveryWords(words).qr_text()?;
+    let PaperBackup::RecoveryWords(words) = PaperBackup::import(&qr)? else {
+        panic!("expected recovery words");
+    };
+    let unlock = DecryptOptions {
+        recovery_words: Some(words.to_string()),
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(Some(copy), Some(output.clone()), "", &unlock)?;
+    assert_eq!(fs::read(&output)?, b"on paper");
+    Ok(())
+}
+
+#[test]
+fn hybrid_recipients_and_password_coexist() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"long-lived archive")?;
+
+    let alice = hybrid::generate_key()?;
+    let bob = hybrid::generate_key()?;
+    let opts = EncryptOptions {
+        recipients: vec![alice.public_key().into(), bob.public_key().into()],
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input.clone()), Some(encrypted.clone()), "pw", &opts)?;
+    assert_eq!(run_inspect(Some(encrypted.clone()))?.key_slots, 3);
+
+    for identity in [&bob, &alice] {
+        let with_key = DecryptOptions {
+            identities: vec![identity.clone().into()],
+            ..DecryptOptions::default()
+        };
+        run_decrypt_with(Some(encrypted.clone()), Some(output.clone()), "", &with_key)?;
+        assert_eq!(fs::read(&output)?, b"long-lived archive");
+        fs::remove_file(&output)?;
+    }
+    run_decrypt(Some(encrypted.clone()), Some(output.clone()), "pw")?;
+    fs::remove_file(&output)?;
+
+    let stranger = DecryptOptions {
+        identities: vec![hybrid::generate_key()?.into()],
+        ..DecryptOptions::default()
+    };
+    let err = run_decrypt_with(Some(encrypted), Some(output.clone()), "", &stranger).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::NotARecipient)
+    ));
+    assert!(!output.exists());
+
+    // Without a password only the recipients can decrypt
+    let keys_only = dir.path().join("keys-only.bin");
+    run_encrypt_with(Some(input.clone()), Some(keys_only.clone()), "", &opts)?;
+    assert_eq!(run_inspect(Some(keys_only.clone()))?.key_slots, 2);
+    assert!(run_decrypt(Some(keys_only.clone()), Some(output.clone()), "").is_err());
+    let with_key = DecryptOptions {
+        identities: vec![alice.into()],
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(Some(keys_only), Some(output.clone()), "", &with_key)?;
+    assert_eq!(fs::read(&output)?, b"long-lived archive");
+
+    // The slot count is a single byte
+    let crowded = dir.path().join("crowded.bin");
+    let too_many = EncryptOptions {
+        recipients: vec![bob.public_key().into(); 255],
+        ..EncryptOptions::default()
+    };
+    let err = run_encrypt_with(Some(input), Some(crowded.clone()), "pw", &too_many).unwrap_err();
+    assert!(err.to_string().contains("at most 255 key slots"));
+    assert!(!crowded.exists());
+    Ok(())
+}
+
+#[test]
+fn ssh_keys_are_recipients() -> anyhow::Result<()> {
+    use rand_core::OsRng;
+    use ssh_key::{Algorithm, LineEnding, PrivateKey};
+
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"for the team")?;
+
+    let ed25519 = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+    let rsa = PrivateKey::random(&mut OsRng, Algorithm::Rsa { hash: None })?;
+    let mut recipients = Vec::new();
+    for (name, key) in [("id_ed25519", &ed25519), ("id_rsa", &rsa)] {
+        let path = dir.path().join(name);
+        key.encrypt(&mut OsRng, "key passphrase")?
+            .write_openssh_file(&path, LineEnding::LF)?;
+        key.public_key()
+            .write_openssh_file(&path.with_extension("pub"))?;
+        recipients.push(recipient::read_recipient(&path.with_extension("pub"))?);
+    }
+    let opts = EncryptOptions {
+        recipients,
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input), Some(encrypted.clone()), "", &opts)?;
+
+    for name in ["id_ed25519", "id_rsa"] {
+        let path = dir.path().join(name);
+        let err = recipient::read_identity(&path, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::KeyPassphraseRequired)
+        ));
+        let with_key = DecryptOptions {
+            identities: vec![recipient::read_identity(&path, Some("key passphrase"))?],
+            ..DecryptOptions::default()
+        };
+        run_decrypt_with(Some(encrypted.clone()), Some(output.clone()), "", &with_key)?;
+        assert_eq!(fs::read(&output)?, b"for the team");
+        fs::remove_file(&output)?;
+    }
+
+    let stranger = DecryptOptions {
+        identities: vec![PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?.into()],
+        ..DecryptOptions::default()
+    };
+    let err = run_decrypt_with(Some(encrypted), Some(output.clone()), "", &stranger).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::NotARecipient)
+    ));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn keyring_contacts_and_identities() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let input = dir.path().join("plain.txt");
+    let encrypted = dir.path().join("cipher.bin");
+    let output = dir.path().join("out.txt");
+    fs::write(&input, b"minutes")?;
+
+    // Alice keeps her identity in her keyring; Bob keeps her public key as a contact
+    let alice_key = hybrid::generate_key()?;
+    let mut alice = Keyring::create(&dir.path().join("alice"), "alice master")?;
+    alice.add_identity("work", alice_key.clone().into())?;
+    alice.save()?;
+    let mut bob = Keyring::create(&dir.path().join("bob"), "bob master")?;
+    bob.add_contact("alice", alice_key.public_key().into())?;
+    bob.save()?;
+
+    let bob = Keyring::open(&dir.path().join("bob"), "bob master")?;
+    let opts = EncryptOptions {
+        recipients: vec![bob.resolve("alice")?],
+        ..EncryptOptions::default()
+    };
+    run_encrypt_with(Some(input.clone()), Some(encrypted.clone()), "", &opts)?;
+
+    let alice = Keyring::open(&dir.path().join("alice"), "alice master")?;
+    let with_keyring = DecryptOptions {
+        identities: alice.identities().map(|(_, i)| i.clone()).collect(),
+        ..DecryptOptions::default()
+    };
+    run_decrypt_with(Some(encrypted), Some(output.clone()), "", &with_keyring)?;
+    assert_eq!(fs::read(&output)?, b"minutes");
+    fs::remove_file(&output)?;
+
+    // Keyring identities do not stand in the way of a file that only has a password
+    let password_only = dir.path().join("password-only.bin");
+    run_encrypt(Some(input), Some(password_only.clone()), "pw")?;
+    let err = run_decrypt_with(
+        Some(password_only.clone()),
+        Some(output.clone()),
+        "",
+        &with_keyring,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::NotARecipient)
+    ));
+    run_decrypt_with(
+        Some(password_only),
+        Some(output.clone()),
+        "pw",
+        &with_keyring,
+    )?;
+    assert_eq!(fs::read(&output)?, b"minutes");
     Ok(())
 }